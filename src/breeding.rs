@@ -1,40 +1,178 @@
-use crate::{creature::Creature, Opts};
+use crate::{
+    creature::{Creature, Diet},
+    genome::{Genome, DIET},
+    lineage::Genealogy,
+    mutation::MutationParams,
+    Opts,
+};
+use clap::ArgEnum;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-impl Creature {
-    pub fn breed(creature_1: &Self, creature_2: &Self, opts: &Opts, rng: &mut impl Rng) -> Self {
-        let factor_1: f64 = rng.gen();
-        let factor_2 = 1.0 - factor_1;
-
-        let mut speed = (creature_1.speed * factor_1) + (creature_2.speed * factor_2);
-        let mut stamina = (creature_1.stamina * factor_1) + (creature_2.stamina * factor_2);
-        let mut fov = (creature_1.fov * factor_1) + (creature_2.fov * factor_2);
-        let mut size = (creature_1.size * factor_1) + (creature_2.size * factor_2);
-
-        let capped_factor = 2.0 / (speed + stamina + fov + size);
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Crossover {
+    /// Every gene is blended with the same random factor
+    Blend,
+    /// Every gene is taken from either parent
+    Uniform,
+    /// Every gene is blended with its own random factor
+    GeneBlend,
+    /// Every gene is picked in the range of the parents extended by alpha times its length
+    BlxAlpha,
+    /// Simulated binary crossover, which spreads children around the parents like a one-point
+    /// crossover would on binary genes
+    Sbx,
+    /// The child is a copy of either parent
+    Clone,
+}
 
-        speed *= capped_factor;
-        stamina *= capped_factor;
-        fov *= capped_factor;
-        size *= capped_factor;
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DietInheritance {
+    /// Diet of the parent that contributed the most to the genes, or a random one if the
+    /// crossover can't tell
+    Dominant,
+    /// Diet of either parent
+    Random,
+    /// Diet of the parent with the most energy left
+    Fitter,
+}
 
-        let diet = if factor_1 >= 0.5 {
-            creature_1.diet
+impl Creature {
+    pub fn breed(
+        creature_1: &Self,
+        creature_2: &Self,
+        genealogy: &mut Genealogy,
+        opts: &Opts,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let mut genome = if opts.diploid {
+            Genome::inherit(&creature_1.genome, &creature_2.genome, rng)
         } else {
-            creature_2.diet
+            crossover(creature_1, creature_2, opts, rng)
         };
 
+        genome.normalise(opts);
+        let ancestry = genealogy.birth(
+            Some([creature_1, creature_2]),
+            Diet::ALL[genome.category(DIET)],
+        );
         Self {
             energy: opts.start_energy,
+            genome,
+            ancestry,
+            species: creature_1.species,
+            strategy: MutationParams::inherited(creature_1.strategy, creature_2.strategy, opts),
+        }
+    }
 
-            speed,
-            stamina,
-            fov,
-            size,
+    /// Child of a single parent, whose genome is an exact copy of its parent's
+    pub fn bud(parent: &Self, genealogy: &mut Genealogy, opts: &Opts) -> Self {
+        let genome = parent.genome.clone();
+        let ancestry = genealogy.birth(Some([parent, parent]), Diet::ALL[genome.category(DIET)]);
+        Self {
+            energy: opts.start_energy,
+            genome,
+            ancestry,
+            species: parent.species,
+            strategy: MutationParams::inherited(parent.strategy, parent.strategy, opts),
+        }
+    }
+}
 
-            diet,
+/// Genome of a child whose genes are combined according to the crossover operator and the diet
+/// inheritance
+fn crossover(
+    creature_1: &Creature,
+    creature_2: &Creature,
+    opts: &Opts,
+    rng: &mut impl Rng,
+) -> Genome {
+    let mut genome = creature_1.genome.clone();
+    let genes_2 = creature_2.genome.continuous_enabled(opts);
 
-            colour: Self::colour(speed, stamina, fov),
+    // Share of the genes coming from the first parent, if it can be told
+    let share_1 = match opts.crossover {
+        Crossover::Blend => {
+            let factor_1: f64 = rng.gen();
+            let factor_2 = 1.0 - factor_1;
+            for (gene, gene_2) in genome.continuous_enabled_mut(opts).zip(genes_2) {
+                *gene = (*gene * factor_1) + (gene_2 * factor_2);
+            }
+            Some(factor_1)
+        }
+
+        Crossover::Uniform => {
+            let (mut from_1, mut count) = (0, 0);
+            for (gene, gene_2) in genome.continuous_enabled_mut(opts).zip(genes_2) {
+                if rng.gen_bool(0.5) {
+                    from_1 += 1;
+                } else {
+                    *gene = gene_2;
+                }
+                count += 1;
+            }
+            Some(from_1 as f64 / count as f64)
+        }
+
+        Crossover::GeneBlend => {
+            let (mut total_1, mut count) = (0.0, 0);
+            for (gene, gene_2) in genome.continuous_enabled_mut(opts).zip(genes_2) {
+                let factor_1: f64 = rng.gen();
+                *gene = (*gene * factor_1) + (gene_2 * (1.0 - factor_1));
+                total_1 += factor_1;
+                count += 1;
+            }
+            Some(total_1 / count as f64)
+        }
+
+        Crossover::BlxAlpha => {
+            for (gene, gene_2) in genome.continuous_enabled_mut(opts).zip(genes_2) {
+                let (min, max) = (gene.min(gene_2), gene.max(gene_2));
+                let extent = (max - min) * opts.blx_alpha;
+                if extent > 0.0 {
+                    *gene = rng.gen_range(min - extent, max + extent);
+                }
+            }
+            None
+        }
+
+        Crossover::Sbx => {
+            let exponent = 1.0 / (opts.sbx_eta + 1.0);
+            for (gene, gene_2) in genome.continuous_enabled_mut(opts).zip(genes_2) {
+                let u: f64 = rng.gen();
+                let beta = if u <= 0.5 {
+                    (2.0 * u).powf(exponent)
+                } else {
+                    (1.0 / (2.0 * (1.0 - u))).powf(exponent)
+                };
+                *gene = 0.5 * ((1.0 + beta) * *gene + (1.0 - beta) * gene_2);
+            }
+            None
+        }
+
+        Crossover::Clone => {
+            if rng.gen_bool(0.5) {
+                Some(1.0)
+            } else {
+                genome = creature_2.genome.clone();
+                Some(0.0)
+            }
+        }
+    };
+
+    let categories_2 = creature_2.genome.categorical_enabled(opts);
+    for (category, category_2) in genome.categorical_enabled_mut(opts).zip(categories_2) {
+        let first = match (opts.diet_inheritance, share_1) {
+            (DietInheritance::Dominant, Some(share_1)) => share_1 >= 0.5,
+            (DietInheritance::Dominant, None) | (DietInheritance::Random, _) => rng.gen_bool(0.5),
+            (DietInheritance::Fitter, _) => creature_1.energy >= creature_2.energy,
+        };
+        if !first {
+            *category = category_2;
         }
     }
+
+    genome
 }
@@ -0,0 +1,251 @@
+use crate::{
+    genome::{Budget, BudgetModel},
+    island::Overrides,
+    reproduction::Evolution,
+    Opts,
+};
+use anyhow::{anyhow, Error};
+use clap::{ArgMatches, FromArgMatches, IntoApp};
+use serde_json::Value;
+use std::{ffi::OsStr, fs, path::Path};
+
+/// Options that apply to the whole simulation rather than to a single island, or that decide
+/// which genes the combined statistics follow, along with those starting with `migration_`
+const GLOBAL: &[&str] = &[
+    "seed",
+    "islands",
+    "island",
+    "migrants",
+    "resume",
+    "generations",
+    "headless",
+    "checkpoint_every",
+    "start_speed",
+    "evolution",
+    "sample_interval",
+    "species_threshold",
+    "diploid",
+    "diet_mode",
+    "food_mix",
+];
+
+impl Opts {
+    /// Parses the command line and merges it over the `--config` file if there is one,
+    /// so that only the flags passed explicitly override the values of the file
+    pub fn load() -> Result<Self, Error> {
+        let matches = Self::into_app().get_matches();
+        let opts = Self::from_arg_matches(&matches);
+
+        let path = match &opts.config {
+            Some(path) => path.clone(),
+            None => {
+                opts.validate()?;
+                return Ok(opts);
+            }
+        };
+
+        let mut merged = merge(&opts, read(&path)?, &matches)?;
+        merged.config = Some(path);
+        merged.validate()?;
+        Ok(merged)
+    }
+
+    /// Options of an island, which are these options with the overrides of the island applied,
+    /// as if it were simulated alone
+    pub fn island(&self, index: usize) -> Result<Self, Error> {
+        let mut merged = match serde_json::to_value(self)? {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        for (key, value) in self
+            .island
+            .get(index)
+            .into_iter()
+            .flat_map(Overrides::values)
+        {
+            if !merged.contains_key(key) {
+                return Err(anyhow!(
+                    "option inconnue `{}` pour l'île {}",
+                    key,
+                    index + 1
+                ));
+            }
+            if GLOBAL.contains(&key.as_str()) || key.starts_with("migration_") {
+                return Err(anyhow!(
+                    "l'option `{}` s'applique à toute la simulation et ne peut pas être propre à l'île {}",
+                    key,
+                    index + 1
+                ));
+            }
+            merged.insert(key.clone(), value.clone());
+        }
+
+        let mut opts: Self = serde_json::from_value(Value::Object(merged))
+            .map_err(|e| anyhow!("options invalides pour l'île {} : {}", index + 1, e))?;
+        opts.config = self.config.clone();
+        opts.islands = 1;
+        opts.island.clear();
+        Ok(opts)
+    }
+
+    /// Checks the options that can't be checked while parsing them
+    fn validate(&self) -> Result<(), Error> {
+        if self.island.len() > self.islands {
+            return Err(anyhow!(
+                "{} îles ont des options propres, mais il n'y a que {} îles",
+                self.island.len(),
+                self.islands
+            ));
+        }
+        self.check_ranges()?;
+        for i in 0..self.island.len() {
+            self.island(i)?
+                .check_ranges()
+                .map_err(|e| anyhow!("options invalides pour l'île {} : {}", i + 1, e))?;
+        }
+        if self.checkpoint_every == Some(0) {
+            return Err(anyhow!("--checkpoint-every doit être d'au moins 1"));
+        }
+        if self.evolution == Evolution::SteadyState && self.food_regrowth <= 0.0 {
+            return Err(anyhow!(
+                "le mode continu nécessite que la nourriture repousse (--food-regrowth)"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the options that must stay within a range or be reachable, which can differ on every
+    /// island
+    fn check_ranges(&self) -> Result<(), Error> {
+        for (name, probability) in &[
+            ("--mutation-probability", self.mutation_probability),
+            (
+                "--gene-mutation-probability",
+                self.gene_mutation_probability,
+            ),
+            (
+                "--mutation-reset-probability",
+                self.mutation_reset_probability,
+            ),
+        ] {
+            if !(0.0..=1.0).contains(probability) {
+                return Err(anyhow!("{} doit être entre 0 et 1", name));
+            }
+        }
+
+        // Factors outside of these ranges would make the schedules grow instead of decay
+        if !(self.mutation_decay > 0.0 && self.mutation_decay <= 1.0) {
+            return Err(anyhow!(
+                "--mutation-decay doit être supérieur à 0 et au plus 1"
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.mutation_floor) {
+            return Err(anyhow!("--mutation-floor doit être entre 0 et 1"));
+        }
+        if !(self.one_fifth_factor > 0.0 && self.one_fifth_factor < 1.0) {
+            return Err(anyhow!(
+                "--one-fifth-factor doit être strictement entre 0 et 1"
+            ));
+        }
+        if !(0.0..).contains(&self.mutation_learning_rate) {
+            return Err(anyhow!("--mutation-learning-rate doit être positif ou nul"));
+        }
+
+        for (name, value) in &[("--sbx-eta", self.sbx_eta), ("--blx-alpha", self.blx_alpha)] {
+            if !(0.0..).contains(value) {
+                return Err(anyhow!("{} doit être positif ou nul", name));
+            }
+        }
+
+        // Children born without energy starve at once, and parents giving more than they have die
+        if self.evolution == Evolution::SteadyState
+            && !(self.reproduction_cost > 0.0
+                && self.reproduction_cost <= self.reproduction_threshold)
+        {
+            return Err(anyhow!(
+                "--reproduction-cost doit être supérieur à 0 et au plus --reproduction-threshold"
+            ));
+        }
+
+        if !(self.budget_exponent > 0.0 && self.budget_exponent.is_finite()) {
+            return Err(anyhow!("--budget-exponent doit être supérieur à 0"));
+        }
+
+        // The soft model can be exceeded and the other models don't scale the genes
+        if matches!(
+            self.budget,
+            BudgetModel::Linear | BudgetModel::Weighted | BudgetModel::Power
+        ) {
+            let max = Budget::Traits.max_cost(self);
+            if self.budget_total > max {
+                return Err(anyhow!(
+                    "--budget-total ne peut pas dépasser {}, le coût des caractéristiques à leur maximum",
+                    max
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the options to a TOML or JSON file depending on its extension, leaving out the
+    /// ones that control the run rather than the simulation so that the file reproduces it
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut opts = self.clone();
+        opts.headless = false;
+        opts.generations = None;
+        opts.resume = None;
+        opts.checkpoint_every = None;
+
+        let contents = if is_json(path) {
+            serde_json::to_string_pretty(&opts)?
+        } else {
+            toml::to_string(&opts)?
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<Value, Error> {
+    let contents = fs::read_to_string(path)?;
+    let value = if is_json(path) {
+        serde_json::from_str(&contents)?
+    } else {
+        toml::from_str(&contents)?
+    };
+    Ok(value)
+}
+
+fn merge(opts: &Opts, file: Value, matches: &ArgMatches) -> Result<Opts, Error> {
+    let mut merged = match serde_json::to_value(opts)? {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    };
+    let file = match file {
+        Value::Object(map) => map,
+        _ => {
+            return Err(anyhow!(
+                "le fichier de configuration doit contenir une table"
+            ))
+        }
+    };
+
+    for (key, value) in file {
+        let key = key.replace('-', "_");
+        if !merged.contains_key(&key) {
+            return Err(anyhow!(
+                "option inconnue `{}` dans le fichier de configuration",
+                key
+            ));
+        }
+        if matches.occurrences_of(key.replace('_', "-")) == 0 {
+            merged.insert(key, value);
+        }
+    }
+
+    Ok(serde_json::from_value(Value::Object(merged))?)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().and_then(OsStr::to_str) == Some("json")
+}
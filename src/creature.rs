@@ -1,74 +1,119 @@
-use crate::Opts;
+use crate::{
+    food::{Food, CARCASS, FOOD_KINDS},
+    genome::{Genome, CARNIVORY, DIET, DISCERNMENT, FOV, SIZE, SPEED, STAMINA},
+    lineage::{Ancestry, Genealogy},
+    mutation::MutationParams,
+    Opts,
+};
+use clap::ArgEnum;
 use nalgebra::{Point2, Vector2};
 use rand::Rng;
-use sdl2::pixels::Color;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Creature {
     pub energy: f64,
-
-    // Capped
-    pub speed: f64,
-    pub stamina: f64,
-    pub fov: f64,
-    pub size: f64,
-
-    // Arbitrary
-    pub diet: Diet,
-
-    pub colour: Color,
+    pub genome: Genome,
+    pub ancestry: Ancestry,
+    /// Id of the species the creature was sorted into
+    pub species: u64,
+    /// Mutation parameters carried by the creature in self-adaptive mode
+    pub strategy: Option<MutationParams>,
 }
 
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[derive(
+    Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, ArgEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
 pub enum Diet {
     Herbivore,
     Carnivore,
+    /// Only eats carcasses, only available with `--scavengers`
+    Scavenger,
+    /// Eats everything, less efficiently, only available with `--omnivores`
+    Omnivore,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct LivingCreature<'a> {
-    pub creature: &'a mut Creature,
-    pub eaten: bool,
-    pub position: Point2<f64>,
-    pub direction: Vector2<f64>,
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DietMode {
+    /// The diet gene picks one of the diets
+    Categorical,
+    /// The carnivory gene sets the share of the energy of meat that is digested, the rest of
+    /// the energy of plants, and the diet follows from it
+    Continuous,
 }
 
-impl Creature {
-    pub fn random(opts: &Opts, rng: &mut impl Rng) -> Self {
-        let mut speed: f64 = rng.gen();
-        let mut stamina: f64 = rng.gen();
-        let mut fov: f64 = rng.gen();
-        let mut size: f64 = rng.gen();
+impl Diet {
+    /// Every diet, in the same order as the categories of the diet gene
+    pub const ALL: &'static [Self] = &[
+        Self::Herbivore,
+        Self::Carnivore,
+        Self::Scavenger,
+        Self::Omnivore,
+    ];
+
+    /// Diet of a creature with the given carnivory in continuous mode
+    pub fn from_carnivory(carnivory: f64) -> Self {
+        if carnivory < 1.0 / 3.0 {
+            Self::Herbivore
+        } else if carnivory > 2.0 / 3.0 {
+            Self::Carnivore
+        } else {
+            Self::Omnivore
+        }
+    }
+}
 
-        let capped_factor = 2.0 / (speed + stamina + fov + size);
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum DeathCause {
+    /// Ran out of energy
+    Starved,
+    /// Eaten alive by another creature
+    Eaten,
+    /// Ran out of energy, then its carcass was eaten to the end, which is recorded when the
+    /// carcass is finished on top of the starvation
+    Scavenged,
+}
 
-        speed *= capped_factor;
-        stamina *= capped_factor;
-        fov *= capped_factor;
-        size *= capped_factor;
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Death {
+    pub cause: DeathCause,
+    /// Diet of the creature that ate it, if any
+    pub killer: Option<Diet>,
+    /// Tick of the generation at which the creature died, or at which its carcass was finished
+    pub tick: usize,
+}
 
-        let diet = if rng.gen_bool(0.5) {
-            Diet::Carnivore
-        } else {
-            Diet::Herbivore
-        };
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LivingCreature {
+    pub creature: Creature,
+    pub eaten: bool,
+    pub death: Option<Death>,
+    pub position: Point2<f64>,
+    pub direction: Vector2<f64>,
+    /// Food eaten during the generation, by type
+    pub meals: Vec<usize>,
+    /// Deaths of the creatures whose carcasses it finished during the generation
+    pub scavenged: Vec<Death>,
+}
 
+impl Creature {
+    /// Creates a founder with random genes
+    pub fn random(opts: &Opts, genealogy: &mut Genealogy, rng: &mut impl Rng) -> Self {
+        let genome = Genome::random(opts, rng);
+        let ancestry = genealogy.birth(None, Diet::ALL[genome.category(DIET)]);
         Self {
             energy: opts.start_energy,
-
-            speed,
-            stamina,
-            fov,
-            size,
-
-            diet,
-
-            colour: Self::colour(speed, stamina, fov),
+            genome,
+            ancestry,
+            species: 0,
+            strategy: MutationParams::carried(opts),
         }
     }
 
     pub fn speed(&self, hurry: bool, opts: &Opts) -> f64 {
-        let speed = self.speed * opts.speed_factor + opts.base_speed;
+        let speed = self.genome.phenotype(SPEED, opts);
         if hurry {
             speed
         } else {
@@ -76,49 +121,103 @@ impl Creature {
         }
     }
 
+    pub fn stamina(&self, opts: &Opts) -> f64 {
+        self.genome.phenotype(STAMINA, opts)
+    }
+
     pub fn fov(&self, opts: &Opts) -> f64 {
-        self.fov * opts.fov_factor + opts.base_fov
+        self.genome.phenotype(FOV, opts)
     }
 
     pub fn size(&self, opts: &Opts) -> f64 {
-        self.size * opts.size_factor + opts.base_size
+        self.genome.phenotype(SIZE, opts)
+    }
+
+    /// Share of the field of view within which the creature recognises toxic food
+    pub fn discernment(&self, opts: &Opts) -> f64 {
+        self.genome.phenotype(DISCERNMENT, opts)
+    }
+
+    pub fn diet(&self) -> Diet {
+        Diet::ALL[self.genome.category(DIET)]
     }
 
+    /// Whether the creature hunts the other one, dead creatures having become carcasses
     pub fn can_prey_on(&self, other: &Self) -> bool {
-        match self.diet {
-            Diet::Herbivore => false,
-            Diet::Carnivore => match (other.diet, other.energy <= 0.0) {
-                (_, true) => true,
-                (Diet::Herbivore, _) => other.size - self.size <= 0.25,
-                (Diet::Carnivore, _) => self.size > other.size,
+        let (size, other_size) = (self.genome.value(SIZE), other.genome.value(SIZE));
+        self.hunts()
+            && other.energy > 0.0
+            && match other.diet() {
+                Diet::Herbivore | Diet::Scavenger => other_size - size <= 0.25,
+                Diet::Carnivore | Diet::Omnivore => size > other_size,
+            }
+    }
+
+    pub fn hunts(&self) -> bool {
+        match self.diet() {
+            Diet::Carnivore | Diet::Omnivore => true,
+            Diet::Herbivore | Diet::Scavenger => false,
+        }
+    }
+
+    pub fn can_eat(&self, food: &Food) -> bool {
+        match self.diet() {
+            Diet::Herbivore => food.kind != CARCASS,
+            Diet::Carnivore | Diet::Scavenger => food.kind == CARCASS,
+            Diet::Omnivore => true,
+        }
+    }
+
+    /// Share of the energy of meat, or of plants, that the creature gets when eating it
+    pub fn digestion(&self, meat: bool, opts: &Opts) -> f64 {
+        match opts.diet_mode {
+            DietMode::Categorical => match self.diet() {
+                Diet::Omnivore => opts.omnivore_efficiency,
+                Diet::Herbivore | Diet::Carnivore | Diet::Scavenger => 1.0,
             },
+            DietMode::Continuous => {
+                let carnivory = self.genome.value(CARNIVORY);
+                if meat {
+                    carnivory
+                } else {
+                    1.0 - carnivory
+                }
+            }
         }
     }
 }
 
-impl<'a> LivingCreature<'a> {
-    pub fn random(
-        creature: &'a mut Creature,
-        x: (f64, f64),
-        y: (f64, f64),
-        rng: &mut impl Rng,
-    ) -> Self {
+impl LivingCreature {
+    pub fn random(creature: Creature, x: (f64, f64), y: (f64, f64), rng: &mut impl Rng) -> Self {
         let x = rng.gen_range(x.0, x.1);
         let y = rng.gen_range(y.0, y.1);
+        Self::new(creature, Point2::new(x, y), rng)
+    }
 
+    /// Places the creature at the given position, heading in a random direction
+    pub fn new(creature: Creature, position: Point2<f64>, rng: &mut impl Rng) -> Self {
         let mut direction =
             Point2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)) - Point2::origin();
-        direction.set_magnitude((1.0 + creature.speed) * 0.75);
+        direction.set_magnitude((1.0 + creature.genome.value(SPEED)) * 0.75);
 
         Self {
             creature,
             eaten: false,
-            position: Point2::new(x, y),
+            death: None,
+            position,
             direction,
+            meals: vec![0; FOOD_KINDS.len()],
+            scavenged: Vec::new(),
         }
     }
 
     pub fn is_dead(&self) -> bool {
         self.eaten || self.creature.energy <= 0.0
     }
+
+    /// Death of the creature, if it died, followed by those of the creatures whose carcasses it
+    /// finished
+    pub fn deaths(&self) -> impl Iterator<Item = Death> + '_ {
+        self.death.into_iter().chain(self.scavenged.iter().copied())
+    }
 }
@@ -1,41 +1,84 @@
 use crate::{
-    creature::{Creature, Diet, LivingCreature},
-    food::Food,
+    creature::{Creature, Diet, DietMode, LivingCreature},
+    food::{Food, CARCASS, FOOD_KINDS},
+    genome::CARNIVORY,
+    world::{Topology, World},
     Opts,
 };
 use anyhow::Error;
+use nalgebra::Point2;
 use sdl2::{gfx::primitives::DrawRenderer, pixels::Color};
 
 const U8_MAX_F64: f64 = u8::MAX as f64;
 
 impl Creature {
-    pub fn colour(speed: f64, stamina: f64, fov: f64) -> Color {
-        let r = (speed * U8_MAX_F64) as u8;
-        let g = (stamina * U8_MAX_F64) as u8;
-        let b = (fov * U8_MAX_F64) as u8;
+    pub fn colour(&self) -> Color {
+        let mut channels = self
+            .genome
+            .continuous()
+            .map(|v| (v * U8_MAX_F64) as u8)
+            .chain(std::iter::repeat(0));
+        let r = channels.next().unwrap();
+        let g = channels.next().unwrap();
+        let b = channels.next().unwrap();
         Color::RGB(r, g, b)
     }
 }
 
-impl LivingCreature<'_> {
+impl LivingCreature {
     pub fn draw(
         &self,
         renderer: &impl DrawRenderer,
         debug: bool,
+        world: &World,
         opts: &Opts,
     ) -> Result<(), Error> {
-        let (x, y) = (self.position.x as _, self.position.y as _);
+        if self.eaten {
+            return Ok(());
+        }
+
+        let radius = if debug {
+            self.creature.fov(opts)
+        } else {
+            self.creature.size(opts)
+        };
+        for position in world.images(self.position, radius) {
+            self.draw_at(renderer, position, debug, opts)?;
+        }
+        Ok(())
+    }
+
+    fn draw_at(
+        &self,
+        renderer: &impl DrawRenderer,
+        position: Point2<f64>,
+        debug: bool,
+        opts: &Opts,
+    ) -> Result<(), Error> {
+        let (x, y) = (position.x as _, position.y as _);
         renderer
-            .filled_circle(x, y, self.creature.size(opts) as _, self.creature.colour)
+            .filled_circle(x, y, self.creature.size(opts) as _, self.creature.colour())
             .map_err(Error::msg)?;
 
         let mut eye_vector = self.direction;
         eye_vector.set_magnitude(self.creature.size(opts) * 0.33);
-        let eye_position = self.position + eye_vector;
+        let eye_position = position + eye_vector;
         let (eye_x, eye_y) = (eye_position.x as _, eye_position.y as _);
-        let eye_colour = match self.creature.diet {
-            Diet::Herbivore => (0, u8::MAX / 2, 0, u8::MAX),
-            Diet::Carnivore => (u8::MAX / 2, 0, 0, u8::MAX),
+        let eye_colour = match (opts.diet_mode, self.creature.diet()) {
+            // From green to red as the creature digests meat better
+            (DietMode::Continuous, _) => {
+                let carnivory = self.creature.genome.value(CARNIVORY);
+                (
+                    (carnivory * U8_MAX_F64 / 2.0) as u8,
+                    ((1.0 - carnivory) * U8_MAX_F64 / 2.0) as u8,
+                    0,
+                    u8::MAX,
+                )
+            }
+            (DietMode::Categorical, Diet::Herbivore) => (0, u8::MAX / 2, 0, u8::MAX),
+            (DietMode::Categorical, Diet::Carnivore) => (u8::MAX / 2, 0, 0, u8::MAX),
+            (DietMode::Categorical, Diet::Scavenger) => (0, 0, u8::MAX / 2, u8::MAX),
+            (DietMode::Categorical, Diet::Omnivore) => (u8::MAX / 2, u8::MAX / 2, 0, u8::MAX),
         };
 
         renderer
@@ -51,7 +94,7 @@ impl LivingCreature<'_> {
                 .map_err(Error::msg)?;
 
             let display_direction = self.direction * 16.0;
-            let display_direction_end = self.position + display_direction;
+            let display_direction_end = position + display_direction;
             let (direction_x, direction_y) =
                 (display_direction_end.x as _, display_direction_end.y as _);
             renderer
@@ -64,14 +107,50 @@ impl LivingCreature<'_> {
 }
 
 impl Food {
+    pub fn draw(&self, renderer: &impl DrawRenderer, world: &World) -> Result<(), Error> {
+        if self.eaten {
+            return Ok(());
+        }
+
+        let (r, g, b) = FOOD_KINDS[self.kind].colour;
+        // Decaying food fades away
+        let freshness = match self.lifetime {
+            Some(lifetime) => 1.0 - self.age as f64 / lifetime.max(1) as f64,
+            None => 1.0,
+        };
+        let alpha = (U8_MAX_F64 * (0.25 + 0.75 * freshness)) as u8;
+        for position in world.images(self.position, self.size) {
+            let (x, y) = (position.x as _, position.y as _);
+            renderer
+                .filled_circle(x, y, self.size as _, (r, g, b, alpha))
+                .map_err(Error::msg)?;
+            if self.kind == CARCASS {
+                renderer
+                    .circle(x, y, self.size as _, (0, 0, 0, alpha))
+                    .map_err(Error::msg)?;
+            } else if self.energy < 0.0 {
+                renderer
+                    .circle(x, y, self.size as i16 + 2, (r, g, b, alpha))
+                    .map_err(Error::msg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl World {
     pub fn draw(&self, renderer: &impl DrawRenderer) -> Result<(), Error> {
-        renderer
-            .filled_circle(
-                self.position.x as _,
-                self.position.y as _,
-                5,
-                (0, 0, 0, u8::MAX),
-            )
-            .map_err(Error::msg)
+        match self.topology {
+            Topology::Bounce | Topology::Walls => renderer
+                .rectangle(
+                    self.x.0 as _,
+                    self.y.0 as _,
+                    self.x.1 as i16 - 1,
+                    self.y.1 as i16 - 1,
+                    Color::BLACK,
+                )
+                .map_err(Error::msg),
+            Topology::Torus | Topology::Open => Ok(()),
+        }
     }
 }
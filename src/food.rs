@@ -1,18 +1,447 @@
-use nalgebra::Point2;
-use rand::Rng;
+use crate::{creature::Creature, world::World, Opts};
+use anyhow::{anyhow, Error};
+use clap::ArgEnum;
+use nalgebra::{Point2, Vector2};
+use rand::{seq::SliceRandom, Rng};
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, f64::consts::PI, str::FromStr};
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Food {
+    /// Whether the food was eaten or rotted away
     pub eaten: bool,
     pub position: Point2<f64>,
+    /// Index of the type of the food in `FOOD_KINDS`
+    pub kind: usize,
+    /// Energy given when fresh, negative for toxic food
+    pub energy: f64,
+    pub size: f64,
+    /// Ticks after which the food rots away, its energy decaying until then
+    pub lifetime: Option<usize>,
+    /// Ticks elapsed since the food appeared
+    pub age: usize,
+}
+
+/// Definition of a type of food, shared by every item of that type
+#[derive(Debug)]
+pub struct FoodKind {
+    /// Identifier used in exported statistics and configuration
+    pub name: &'static str,
+    /// Name displayed in charts and progress lines
+    pub label: &'static str,
+    pub colour: (u8, u8, u8),
+    pub size: f64,
+    pub energy: fn(&Opts) -> f64,
+    pub lifetime: fn(&Opts) -> Option<usize>,
+    /// Most energy that can be eaten at once, the rest staying for later
+    pub portion: fn(&Opts) -> f64,
+}
+
+pub const PLANT: usize = 0;
+pub const FRUIT: usize = 1;
+pub const TOXIC: usize = 2;
+pub const CARCASS: usize = 3;
+
+pub const FOOD_KINDS: &[FoodKind] = &[
+    FoodKind {
+        name: "plant",
+        label: "Plantes",
+        colour: (0, 0, 0),
+        size: 5.0,
+        energy: |opts| opts.plant_energy,
+        lifetime: |opts| opts.plant_lifetime,
+        portion: |_| f64::INFINITY,
+    },
+    FoodKind {
+        name: "fruit",
+        label: "Fruits",
+        colour: (230, 140, 30),
+        size: 3.0,
+        energy: |opts| opts.fruit_energy,
+        lifetime: |opts| Some(opts.fruit_lifetime),
+        portion: |_| f64::INFINITY,
+    },
+    FoodKind {
+        name: "toxic",
+        label: "Toxiques",
+        colour: (150, 40, 170),
+        size: 5.0,
+        energy: |opts| -opts.toxic_energy,
+        lifetime: |_| None,
+        portion: |_| f64::INFINITY,
+    },
+    // Left by starved creatures, never spawned, see `Food::carcass` for the energy and size
+    FoodKind {
+        name: "carcass",
+        label: "Carcasses",
+        colour: (120, 70, 30),
+        size: 0.0,
+        energy: |_| 0.0,
+        lifetime: |opts| Some(opts.carcass_lifetime),
+        portion: |opts| opts.carcass_portion,
+    },
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FoodDistribution {
+    /// Food is spread evenly over the world
+    Uniform,
+    /// Food is clustered around random points that move every generation
+    Patches,
+    /// Food is more likely to appear on the fertile areas of a fixed noise map
+    Fertility,
+    /// Food grows around fixed bushes
+    Bushes,
+}
+
+/// Share of every type of food, 0 by default, written as `plant=1,toxic=0.2`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FoodMix(Vec<f64>);
+
+/// Positions of the bushes, written as `250:250,750:500`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Bushes(Vec<Point2<f64>>);
+
+/// Where food appears on an island, kept for the whole simulation so that fixed features stay
+/// in place
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoodMap {
+    /// Centres of the patches of the current generation, or of the bushes
+    centres: Vec<Point2<f64>>,
+    /// Fertility at the corners of the cells of the noise map, row by row
+    fertility: Vec<f64>,
+    columns: usize,
 }
 
 impl Food {
-    pub fn random(x: (f64, f64), y: (f64, f64), rng: &mut impl Rng) -> Self {
-        let x = rng.gen_range(x.0, x.1);
-        let y = rng.gen_range(y.0, y.1);
+    pub fn new(kind: usize, position: Point2<f64>, opts: &Opts) -> Self {
+        let def = &FOOD_KINDS[kind];
         Self {
             eaten: false,
-            position: Point2::new(x, y),
+            position,
+            kind,
+            energy: (def.energy)(opts),
+            size: def.size,
+            lifetime: (def.lifetime)(opts),
+            age: 0,
+        }
+    }
+
+    /// Body left by a creature that starved, holding a share of the energy of its size
+    pub fn carcass(creature: &Creature, position: Point2<f64>, opts: &Opts) -> Self {
+        let size = creature.size(opts);
+        Self {
+            energy: opts.carcass_share * opts.body_energy * size,
+            size: size * 0.8,
+            ..Self::new(CARCASS, position, opts)
+        }
+    }
+
+    /// Energy given if eaten now, which decays linearly over the lifetime of the food
+    pub fn value(&self) -> f64 {
+        match self.lifetime {
+            Some(lifetime) => {
+                self.energy * (1.0 - self.age as f64 / lifetime.max(1) as f64).max(0.0)
+            }
+            None => self.energy,
+        }
+    }
+
+    /// Eats up to `portion` of the energy of the food and returns the energy eaten, the food
+    /// being gone once nothing is left
+    pub fn bite(&mut self, portion: f64) -> f64 {
+        let value = self.value();
+        if value <= portion {
+            self.eaten = true;
+            value
+        } else {
+            self.energy *= 1.0 - portion / value;
+            portion
+        }
+    }
+
+    /// Ages the food by a tick, after which it may have rotted away
+    pub fn age(&mut self) {
+        self.age += 1;
+        if matches!(self.lifetime, Some(lifetime) if self.age >= lifetime) {
+            self.eaten = true;
         }
     }
 }
+
+impl FoodMix {
+    /// Whether some of the new items can be of the given type
+    pub fn includes(&self, kind: usize) -> bool {
+        self.0[kind] > 0.0
+    }
+
+    /// Picks the type of a new item, without drawing anything when there is only one
+    fn pick(&self, rng: &mut impl Rng) -> usize {
+        let total: f64 = self.0.iter().sum();
+        let mut kinds = self.0.iter().enumerate().filter(|(_, &w)| w > 0.0);
+        match (kinds.next(), kinds.next()) {
+            (None, _) => PLANT,
+            (Some((kind, _)), None) => kind,
+            _ => {
+                let mut value = rng.gen_range(0.0, total);
+                for (kind, &weight) in self.0.iter().enumerate() {
+                    if value < weight {
+                        return kind;
+                    }
+                    value -= weight;
+                }
+                self.0.iter().rposition(|&w| w > 0.0).unwrap()
+            }
+        }
+    }
+}
+
+impl FromStr for FoodMix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = vec![0.0; FOOD_KINDS.len()];
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (name, weight) = match pair.find('=') {
+                Some(i) => (pair[..i].trim(), pair[i + 1..].trim()),
+                None => {
+                    return Err(anyhow!(
+                        "proportion invalide `{}`, format attendu : type=proportion",
+                        pair
+                    ))
+                }
+            };
+            let kind = FOOD_KINDS
+                .iter()
+                .position(|def| def.name == name)
+                .filter(|&kind| kind != CARCASS)
+                .ok_or_else(|| anyhow!("type de nourriture inconnu `{}`", name))?;
+            weights[kind] = match weight.parse::<f64>()? {
+                weight if weight.is_finite() && weight >= 0.0 => weight,
+                _ => {
+                    return Err(anyhow!(
+                        "proportion invalide `{}`, elle doit être un nombre positif ou nul",
+                        pair
+                    ))
+                }
+            };
+        }
+        if weights.iter().sum::<f64>() <= 0.0 {
+            return Err(anyhow!(
+                "au moins un type de nourriture doit avoir une proportion non nulle"
+            ));
+        }
+        Ok(Self(weights))
+    }
+}
+
+impl TryFrom<String> for FoodMix {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FoodMix> for String {
+    fn from(mix: FoodMix) -> Self {
+        FOOD_KINDS
+            .iter()
+            .zip(&mix.0)
+            .filter(|(_, &w)| w != 0.0)
+            .map(|(def, w)| format!("{}={}", def.name, w))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl FromStr for Bushes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|point| {
+                let (x, y) = match point.find(':') {
+                    Some(i) => (point[..i].trim(), point[i + 1..].trim()),
+                    None => {
+                        return Err(anyhow!(
+                            "buisson invalide `{}`, format attendu : x:y",
+                            point
+                        ))
+                    }
+                };
+                Ok(Point2::new(x.parse()?, y.parse()?))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl TryFrom<String> for Bushes {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Bushes> for String {
+    fn from(bushes: Bushes) -> Self {
+        bushes
+            .0
+            .iter()
+            .map(|p| format!("{}:{}", p.x, p.y))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl FoodMap {
+    /// Draws the fixed features of the distribution, which are the bushes placed at random when
+    /// none are given and the noise map
+    pub fn new(world: &World, opts: &Opts, rng: &mut impl Rng) -> Self {
+        let mut map = Self {
+            centres: Vec::new(),
+            fertility: Vec::new(),
+            columns: 0,
+        };
+
+        match opts.food_distribution {
+            FoodDistribution::Uniform | FoodDistribution::Patches => (),
+            FoodDistribution::Fertility => {
+                let scale = opts.noise_scale.max(1.0);
+                map.columns = (world.width() / scale).ceil() as usize + 1;
+                let rows = (world.height() / scale).ceil() as usize + 1;
+                // Squared so that fertile areas stand out
+                map.fertility = (0..map.columns * rows)
+                    .map(|_| rng.gen::<f64>().powi(2))
+                    .collect();
+            }
+            FoodDistribution::Bushes => {
+                map.centres = match &opts.bushes {
+                    Some(Bushes(bushes)) if !bushes.is_empty() => bushes.clone(),
+                    _ => (0..opts.food_patches.max(1))
+                        .map(|_| random_position(world, rng))
+                        .collect(),
+                };
+            }
+        }
+        map
+    }
+
+    /// Centres of the patches of the current generation, or of the bushes
+    pub fn centres(&self) -> &[Point2<f64>] {
+        &self.centres
+    }
+
+    /// Replaces the food with `qty` fresh items, moving the patches beforehand
+    pub fn spawn(
+        &mut self,
+        food_buf: &mut Vec<Food>,
+        qty: usize,
+        world: &World,
+        opts: &Opts,
+        rng: &mut impl Rng,
+    ) {
+        if opts.food_distribution == FoodDistribution::Patches {
+            self.centres = (0..opts.food_patches.max(1))
+                .map(|_| random_position(world, rng))
+                .collect();
+        }
+
+        food_buf.clear();
+        for _ in 0..qty {
+            food_buf.push(self.food(world, opts, rng));
+        }
+    }
+
+    /// Adds the food that grew back during a tick, `food_regrowth` items on average, without
+    /// going over the cap
+    pub fn regrow(&self, food_buf: &mut Vec<Food>, world: &World, opts: &Opts, rng: &mut impl Rng) {
+        let rate = opts.food_regrowth;
+        let grown = rate.floor() as usize + rng.gen_bool(rate.fract()) as usize;
+        let cap = opts.food_cap.unwrap_or(opts.food_count);
+
+        let available = food_buf
+            .iter()
+            .filter(|f| !f.eaten && f.kind != CARCASS)
+            .count();
+        for _ in 0..grown.min(cap.saturating_sub(available)) {
+            food_buf.push(self.food(world, opts, rng));
+        }
+    }
+
+    fn food(&self, world: &World, opts: &Opts, rng: &mut impl Rng) -> Food {
+        let position = match opts.food_distribution {
+            FoodDistribution::Uniform => random_position(world, rng),
+
+            FoodDistribution::Patches => {
+                let centre = self.centres.choose(rng).unwrap();
+                let offset = Vector2::new(
+                    rng.sample::<f64, _>(StandardNormal),
+                    rng.sample::<f64, _>(StandardNormal),
+                );
+                inside(*centre + offset * opts.patch_radius, world)
+            }
+
+            // Rejection sampling, which accepts points in proportion to their fertility
+            FoodDistribution::Fertility => loop {
+                let position = random_position(world, rng);
+                if rng.gen::<f64>() < self.fertility(position, world, opts) {
+                    break position;
+                }
+            },
+
+            FoodDistribution::Bushes => {
+                let centre = self.centres.choose(rng).unwrap();
+                let angle = rng.gen_range(0.0, 2.0 * PI);
+                let distance = rng.gen::<f64>().sqrt() * opts.patch_radius;
+                inside(
+                    *centre + Vector2::new(angle.cos(), angle.sin()) * distance,
+                    world,
+                )
+            }
+        };
+
+        let kind = opts.food_mix.as_ref().map_or(PLANT, |mix| mix.pick(rng));
+        Food::new(kind, position, opts)
+    }
+
+    /// Fertility of a position, interpolated smoothly between the corners of its cell
+    fn fertility(&self, position: Point2<f64>, world: &World, opts: &Opts) -> f64 {
+        let scale = opts.noise_scale.max(1.0);
+        let x = (position.x - world.x.0) / scale;
+        let y = (position.y - world.y.0) / scale;
+        let (column, row) = (x.floor() as usize, y.floor() as usize);
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(x.fract()), smooth(y.fract()));
+
+        let corner = |c: usize, r: usize| self.fertility[r * self.columns + c];
+        let top = corner(column, row) * (1.0 - tx) + corner(column + 1, row) * tx;
+        let bottom = corner(column, row + 1) * (1.0 - tx) + corner(column + 1, row + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+fn random_position(world: &World, rng: &mut impl Rng) -> Point2<f64> {
+    let x = rng.gen_range(world.x.0, world.x.1);
+    let y = rng.gen_range(world.y.0, world.y.1);
+    Point2::new(x, y)
+}
+
+/// Brings a position around a patch or a bush back inside the world, across the edges on a torus
+fn inside(mut position: Point2<f64>, world: &World) -> Point2<f64> {
+    if world.wraps() {
+        position.x = world.x.0 + (position.x - world.x.0).rem_euclid(world.width());
+        position.y = world.y.0 + (position.y - world.y.0).rem_euclid(world.height());
+    } else {
+        position.x = position.x.max(world.x.0).min(world.x.1);
+        position.y = position.y.max(world.y.0).min(world.y.1);
+    }
+    position
+}
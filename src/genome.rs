@@ -0,0 +1,643 @@
+use crate::{
+    creature::{Diet, DietMode},
+    food::TOXIC,
+    Opts,
+};
+use anyhow::{anyhow, Error};
+use clap::ArgEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, str::FromStr};
+
+/// Definition of a gene, shared by every genome
+#[derive(Debug)]
+pub struct GeneDef {
+    /// Identifier used in exported statistics and configuration
+    pub name: &'static str,
+    /// Name displayed in charts and progress lines
+    pub label: &'static str,
+    pub kind: GeneKind,
+    /// Whether the gene has any effect with the options, the genes that don't being left out of
+    /// the genetic distance, mutations, crossovers and statistics
+    pub enabled: fn(&Opts) -> bool,
+}
+
+#[derive(Debug)]
+pub enum GeneKind {
+    Continuous {
+        /// Values are kept within these bounds, once the budget has been applied
+        bounds: (f64, f64),
+        budget: Option<Budget>,
+        /// Value that the gene takes in the simulation
+        phenotype: fn(f64, &Opts) -> f64,
+    },
+    Categorical {
+        categories: &'static [&'static str],
+        /// Whether creatures can be born with or mutate into a category, given the options
+        enabled: fn(usize, &Opts) -> bool,
+    },
+}
+
+/// Group of continuous genes whose values share a limited total, which keeps creatures from
+/// becoming "perfect" over time
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Budget {
+    Traits,
+}
+
+/// How the cost of the genes of a budget group is computed and limited
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BudgetModel {
+    /// The values add up to the total
+    Linear,
+    /// The values multiplied by their weight add up to the total
+    Weighted,
+    /// The values raised to the exponent and multiplied by their weight add up to the total
+    Power,
+    /// Like `Weighted`, but the total can be exceeded at the price of extra energy every tick
+    Soft,
+    /// The values are only kept within their bounds
+    None,
+}
+
+/// Expression of a continuous gene from its two alleles in diploid mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dominance {
+    /// The mean of the alleles is expressed
+    Additive,
+    /// The highest allele is expressed
+    Dominant,
+    /// The highest allele is only expressed if both alleles are high, so the lowest one is
+    Recessive,
+}
+
+/// Cost of each gene in the weighted budget models, 1 by default, written as `speed=1.5,size=2`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TraitWeights(Vec<f64>);
+
+pub const SPEED: usize = 0;
+pub const STAMINA: usize = 1;
+pub const FOV: usize = 2;
+pub const SIZE: usize = 3;
+pub const DIET: usize = 4;
+pub const DISCERNMENT: usize = 5;
+pub const CARNIVORY: usize = 6;
+
+pub const GENES: &[GeneDef] = &[
+    GeneDef {
+        name: "speed",
+        label: "Vitesse",
+        kind: GeneKind::Continuous {
+            bounds: (0.0, 2.0),
+            budget: Some(Budget::Traits),
+            phenotype: |v, opts| v * opts.speed_factor + opts.base_speed,
+        },
+        enabled: |_| true,
+    },
+    GeneDef {
+        name: "stamina",
+        label: "Endurance",
+        kind: GeneKind::Continuous {
+            bounds: (0.0, 2.0),
+            budget: Some(Budget::Traits),
+            phenotype: |v, opts| v * opts.stamina_factor,
+        },
+        enabled: |_| true,
+    },
+    GeneDef {
+        name: "fov",
+        label: "Champ de vision",
+        kind: GeneKind::Continuous {
+            bounds: (0.0, 2.0),
+            budget: Some(Budget::Traits),
+            phenotype: |v, opts| v * opts.fov_factor + opts.base_fov,
+        },
+        enabled: |_| true,
+    },
+    GeneDef {
+        name: "size",
+        label: "Taille",
+        kind: GeneKind::Continuous {
+            bounds: (0.0, 2.0),
+            budget: Some(Budget::Traits),
+            phenotype: |v, opts| v * opts.size_factor + opts.base_size,
+        },
+        enabled: |_| true,
+    },
+    GeneDef {
+        name: "diet",
+        label: "Alimentation",
+        kind: GeneKind::Categorical {
+            categories: &["herbivore", "carnivore", "scavenger", "omnivore"],
+            enabled: |category, opts| match Diet::ALL[category] {
+                Diet::Herbivore | Diet::Carnivore => true,
+                Diet::Scavenger => opts.scavengers,
+                Diet::Omnivore => opts.omnivores,
+            },
+        },
+        enabled: |opts| opts.diet_mode == DietMode::Categorical,
+    },
+    GeneDef {
+        name: "discernment",
+        label: "Discernement",
+        kind: GeneKind::Continuous {
+            bounds: (0.0, 1.0),
+            budget: None,
+            phenotype: |v, _| v,
+        },
+        enabled: |opts| matches!(&opts.food_mix, Some(mix) if mix.includes(TOXIC)),
+    },
+    GeneDef {
+        name: "carnivory",
+        label: "Carnivorie",
+        kind: GeneKind::Continuous {
+            bounds: (0.0, 1.0),
+            budget: None,
+            phenotype: |v, _| v,
+        },
+        enabled: |opts| opts.diet_mode == DietMode::Continuous,
+    },
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Gene {
+    Continuous(f64),
+    Categorical(usize),
+}
+
+/// Genes of a creature, in the same order as `GENES`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Genome {
+    genes: Vec<Gene>,
+    /// Both alleles of every gene in diploid mode, from which `genes` are expressed
+    alleles: Option<Vec<[Gene; 2]>>,
+}
+
+impl GeneDef {
+    pub fn is_enabled(&self, opts: &Opts) -> bool {
+        (self.enabled)(opts)
+    }
+}
+
+impl GeneKind {
+    /// Categories of a categorical gene that are enabled with the options
+    pub fn enabled(&self, opts: &Opts) -> Vec<usize> {
+        match self {
+            Self::Categorical {
+                categories,
+                enabled,
+            } => (0..categories.len())
+                .filter(|&category| enabled(category, opts))
+                .collect(),
+            Self::Continuous { .. } => Vec::new(),
+        }
+    }
+}
+
+impl Budget {
+    const ALL: &'static [Self] = &[Self::Traits];
+
+    pub fn total(self, opts: &Opts) -> f64 {
+        match self {
+            Self::Traits => opts.budget_total,
+        }
+    }
+
+    /// Cost of the genes of the group when all of them are at their upper bound
+    pub fn max_cost(self, opts: &Opts) -> f64 {
+        self.genes()
+            .map(|i| match GENES[i].kind {
+                GeneKind::Continuous { bounds, .. } => gene_cost(i, bounds.1, opts),
+                GeneKind::Categorical { .. } => 0.0,
+            })
+            .sum()
+    }
+
+    /// Indices of the genes of the group
+    fn genes(self) -> impl Iterator<Item = usize> {
+        GENES
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, def)| match def.kind {
+                GeneKind::Continuous {
+                    budget: Some(budget),
+                    ..
+                } if budget == self => Some(i),
+                _ => None,
+            })
+    }
+}
+
+impl TraitWeights {
+    pub fn get(&self, gene: usize) -> f64 {
+        self.0[gene]
+    }
+}
+
+impl FromStr for TraitWeights {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = vec![1.0; GENES.len()];
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (name, weight) = match pair.find('=') {
+                Some(i) => (pair[..i].trim(), pair[i + 1..].trim()),
+                None => {
+                    return Err(anyhow!(
+                        "poids invalide `{}`, format attendu : nom=poids",
+                        pair
+                    ))
+                }
+            };
+            let gene = gene(name).ok_or_else(|| anyhow!("gène inconnu `{}`", name))?;
+            weights[gene] = match weight.parse::<f64>()? {
+                weight if weight.is_finite() && weight >= 0.0 => weight,
+                _ => {
+                    return Err(anyhow!(
+                        "poids invalide `{}`, il doit être un nombre positif ou nul",
+                        pair
+                    ))
+                }
+            };
+        }
+        Ok(Self(weights))
+    }
+}
+
+impl TryFrom<String> for TraitWeights {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TraitWeights> for String {
+    fn from(weights: TraitWeights) -> Self {
+        GENES
+            .iter()
+            .zip(&weights.0)
+            .filter(|(_, &w)| w != 1.0)
+            .map(|(def, w)| format!("{}={}", def.name, w))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Genome {
+    /// Random genes, with every budget group starting exactly at its total whatever the model,
+    /// and in diploid mode two such sets of alleles
+    pub fn random(opts: &Opts, rng: &mut impl Rng) -> Self {
+        let mut haplotype = || {
+            let genes = GENES
+                .iter()
+                .map(|def| match def.kind {
+                    GeneKind::Continuous { bounds, .. } => {
+                        Gene::Continuous(bounds.0 + rng.gen::<f64>() * (bounds.1 - bounds.0))
+                    }
+                    GeneKind::Categorical { .. } => {
+                        let enabled = def.kind.enabled(opts);
+                        Gene::Categorical(enabled[rng.gen_range(0, enabled.len())])
+                    }
+                })
+                .collect();
+
+            let mut genome = Self {
+                genes,
+                alleles: None,
+            };
+            if let BudgetModel::Soft | BudgetModel::None = opts.budget {
+                for &group in Budget::ALL {
+                    genome.scale(group, opts);
+                }
+            }
+            genome.normalise_haploid(opts);
+            genome.genes
+        };
+
+        let genes = haplotype();
+        if !opts.diploid {
+            return Self {
+                genes,
+                alleles: None,
+            };
+        }
+
+        let alleles = genes.iter().copied().zip(haplotype()).map(|(a, b)| [a, b]);
+        let mut genome = Self {
+            alleles: Some(alleles.collect()),
+            genes,
+        };
+        genome.normalise(opts);
+        genome
+    }
+
+    /// Genome of a child in diploid mode, which gets a random allele of every gene from each
+    /// parent, parents without alleles being homozygous
+    pub fn inherit(parent_1: &Self, parent_2: &Self, rng: &mut impl Rng) -> Self {
+        let alleles = (0..GENES.len())
+            .map(|i| {
+                [
+                    parent_1.alleles(i)[rng.gen_range(0, 2)],
+                    parent_2.alleles(i)[rng.gen_range(0, 2)],
+                ]
+            })
+            .collect();
+        Self {
+            genes: parent_1.genes.clone(),
+            alleles: Some(alleles),
+        }
+    }
+
+    pub fn genes(&self) -> &[Gene] {
+        &self.genes
+    }
+
+    pub fn genes_mut(&mut self) -> &mut [Gene] {
+        &mut self.genes
+    }
+
+    /// Both alleles of a gene, which are the same without diploidy
+    pub fn alleles(&self, gene: usize) -> [Gene; 2] {
+        match &self.alleles {
+            Some(alleles) => alleles[gene],
+            None => [self.genes[gene]; 2],
+        }
+    }
+
+    /// Alleles that mutations apply to, which are the genes themselves without diploidy
+    pub fn alleles_mut(&mut self, gene: usize) -> &mut [Gene] {
+        match &mut self.alleles {
+            Some(alleles) => &mut alleles[gene],
+            None => std::slice::from_mut(&mut self.genes[gene]),
+        }
+    }
+
+    pub fn is_diploid(&self) -> bool {
+        self.alleles.is_some()
+    }
+
+    pub fn value(&self, gene: usize) -> f64 {
+        match self.genes[gene] {
+            Gene::Continuous(value) => value,
+            Gene::Categorical(_) => panic!("gene `{}` isn't continuous", GENES[gene].name),
+        }
+    }
+
+    pub fn category(&self, gene: usize) -> usize {
+        match self.genes[gene] {
+            Gene::Categorical(category) => category,
+            Gene::Continuous(_) => panic!("gene `{}` isn't categorical", GENES[gene].name),
+        }
+    }
+
+    pub fn phenotype(&self, gene: usize, opts: &Opts) -> f64 {
+        match GENES[gene].kind {
+            GeneKind::Continuous { phenotype, .. } => phenotype(self.value(gene), opts),
+            GeneKind::Categorical { .. } => self.category(gene) as f64,
+        }
+    }
+
+    /// Values of every continuous gene
+    pub fn continuous(&self) -> impl Iterator<Item = f64> + '_ {
+        self.genes.iter().filter_map(|g| match g {
+            Gene::Continuous(value) => Some(*value),
+            Gene::Categorical(_) => None,
+        })
+    }
+
+    /// Values of the continuous genes that are enabled with the options
+    pub fn continuous_enabled<'a>(&'a self, opts: &'a Opts) -> impl Iterator<Item = f64> + 'a {
+        GENES
+            .iter()
+            .zip(&self.genes)
+            .filter_map(move |(def, g)| match g {
+                Gene::Continuous(value) if def.is_enabled(opts) => Some(*value),
+                _ => None,
+            })
+    }
+
+    /// Values of the categorical genes that are enabled with the options
+    pub fn categorical_enabled<'a>(&'a self, opts: &'a Opts) -> impl Iterator<Item = usize> + 'a {
+        GENES
+            .iter()
+            .zip(&self.genes)
+            .filter_map(move |(def, g)| match g {
+                Gene::Categorical(category) if def.is_enabled(opts) => Some(*category),
+                _ => None,
+            })
+    }
+
+    pub fn continuous_enabled_mut<'a>(
+        &'a mut self,
+        opts: &'a Opts,
+    ) -> impl Iterator<Item = &'a mut f64> + 'a {
+        GENES
+            .iter()
+            .zip(self.genes.iter_mut())
+            .filter_map(move |(def, g)| match g {
+                Gene::Continuous(value) if def.is_enabled(opts) => Some(value),
+                _ => None,
+            })
+    }
+
+    pub fn categorical_enabled_mut<'a>(
+        &'a mut self,
+        opts: &'a Opts,
+    ) -> impl Iterator<Item = &'a mut usize> + 'a {
+        GENES
+            .iter()
+            .zip(self.genes.iter_mut())
+            .filter_map(move |(def, g)| match g {
+                Gene::Categorical(category) if def.is_enabled(opts) => Some(category),
+                _ => None,
+            })
+    }
+
+    /// Expresses the alleles in diploid mode, then applies the budget model and brings every
+    /// gene back within its bounds, which must be done after any change
+    pub fn normalise(&mut self, opts: &Opts) {
+        if !opts.diploid {
+            self.alleles = None;
+            self.normalise_haploid(opts);
+            return;
+        }
+
+        let genes = &self.genes;
+        let alleles = self
+            .alleles
+            .get_or_insert_with(|| genes.iter().map(|&g| [g, g]).collect());
+        for ((i, def), pair) in GENES.iter().enumerate().zip(alleles.iter_mut()) {
+            for allele in pair.iter_mut() {
+                if let (GeneKind::Continuous { bounds, .. }, Gene::Continuous(value)) =
+                    (&def.kind, allele)
+                {
+                    *value = value.max(bounds.0).min(bounds.1);
+                }
+            }
+            self.genes[i] = express(i, *pair, opts);
+        }
+        self.normalise_haploid(opts);
+    }
+
+    fn normalise_haploid(&mut self, opts: &Opts) {
+        for (def, gene) in GENES.iter().zip(self.genes.iter_mut()) {
+            if let (GeneKind::Continuous { bounds, .. }, Gene::Continuous(value)) =
+                (&def.kind, gene)
+            {
+                *value = value.max(bounds.0);
+            }
+        }
+
+        match opts.budget {
+            BudgetModel::Linear | BudgetModel::Weighted | BudgetModel::Power => {
+                for &group in Budget::ALL {
+                    self.scale(group, opts);
+                }
+            }
+            BudgetModel::Soft | BudgetModel::None => (),
+        }
+
+        for (def, gene) in GENES.iter().zip(self.genes.iter_mut()) {
+            if let (GeneKind::Continuous { bounds, .. }, Gene::Continuous(value)) =
+                (&def.kind, gene)
+            {
+                *value = value.min(bounds.1);
+            }
+        }
+
+        if opts.diet_mode == DietMode::Continuous {
+            let diet = Diet::from_carnivory(self.value(CARNIVORY));
+            let category = Diet::ALL.iter().position(|&d| d == diet).unwrap();
+            self.genes[DIET] = Gene::Categorical(category);
+        }
+    }
+
+    /// Cost of the genes of a budget group, which the budget model compares to its total
+    pub fn cost(&self, group: Budget, opts: &Opts) -> f64 {
+        group
+            .genes()
+            .map(|i| gene_cost(i, self.value(i), opts))
+            .sum()
+    }
+
+    /// Extra energy spent every tick for discerning toxic food and for exceeding the total of a
+    /// soft budget
+    pub fn upkeep(&self, opts: &Opts) -> f64 {
+        let discernment = if GENES[DISCERNMENT].is_enabled(opts) {
+            self.value(DISCERNMENT) * opts.discernment_cost
+        } else {
+            0.0
+        };
+        if opts.budget != BudgetModel::Soft {
+            return discernment;
+        }
+
+        Budget::ALL
+            .iter()
+            .map(|&group| (self.cost(group, opts) - group.total(opts)).max(0.0))
+            .sum::<f64>()
+            * opts.budget_penalty
+            + discernment
+    }
+
+    /// Scales the genes of a budget group so that their cost matches its total
+    fn scale(&mut self, group: Budget, opts: &Opts) {
+        // Genes scaled past their upper bound are capped and the rest of the budget goes to the
+        // others, until none of them is capped anymore
+        let mut capped: Vec<usize> = Vec::new();
+        loop {
+            let capped_cost: f64 = capped
+                .iter()
+                .map(|&i| gene_cost(i, self.value(i), opts))
+                .sum();
+            let free: Vec<usize> = group.genes().filter(|i| !capped.contains(i)).collect();
+            let cost: f64 = free
+                .iter()
+                .map(|&i| gene_cost(i, self.value(i), opts))
+                .sum();
+            if cost <= 0.0 {
+                return;
+            }
+
+            let remaining = (group.total(opts) - capped_cost).max(0.0);
+            let capped_factor = match opts.budget {
+                BudgetModel::Power => (remaining / cost).powf(1.0 / opts.budget_exponent),
+                _ => remaining / cost,
+            };
+            let mut overflowed = false;
+            for i in free {
+                if let (GeneKind::Continuous { bounds, .. }, Gene::Continuous(value)) =
+                    (&GENES[i].kind, &mut self.genes[i])
+                {
+                    *value *= capped_factor;
+                    if *value > bounds.1 {
+                        *value = bounds.1;
+                        capped.push(i);
+                        overflowed = true;
+                    }
+                }
+            }
+            if !overflowed {
+                return;
+            }
+        }
+    }
+}
+
+/// Cost of a single gene of a budget group with the given value
+fn gene_cost(gene: usize, value: f64, opts: &Opts) -> f64 {
+    let weight = opts.trait_weights.as_ref().map_or(1.0, |w| w.get(gene));
+    match opts.budget {
+        BudgetModel::Linear => value,
+        BudgetModel::Weighted | BudgetModel::Soft | BudgetModel::None => value * weight,
+        BudgetModel::Power => value.powf(opts.budget_exponent) * weight,
+    }
+}
+
+/// Value expressed by the two alleles of a gene
+fn express(gene: usize, alleles: [Gene; 2], opts: &Opts) -> Gene {
+    match alleles {
+        [Gene::Continuous(a), Gene::Continuous(b)] => Gene::Continuous(match opts.dominance {
+            Dominance::Additive => (a + b) / 2.0,
+            Dominance::Dominant => a.max(b),
+            Dominance::Recessive => a.min(b),
+        }),
+        [Gene::Categorical(a), Gene::Categorical(b)] => {
+            let dominant = dominant_category(gene, opts);
+            if b == dominant {
+                Gene::Categorical(b)
+            } else {
+                Gene::Categorical(a)
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Category that is expressed as soon as one of the alleles of a categorical gene carries it
+pub fn dominant_category(gene: usize, opts: &Opts) -> usize {
+    if gene == DIET {
+        Diet::ALL
+            .iter()
+            .position(|&d| d == opts.dominant_diet)
+            .unwrap()
+    } else {
+        0
+    }
+}
+
+/// Index of the gene with the given name
+pub fn gene(name: &str) -> Option<usize> {
+    GENES.iter().position(|def| def.name == name)
+}
+
+/// Definitions of the continuous genes, in the same order as `Genome::continuous`
+pub fn continuous() -> impl Iterator<Item = &'static GeneDef> {
+    GENES
+        .iter()
+        .filter(|def| matches!(def.kind, GeneKind::Continuous { .. }))
+}
@@ -0,0 +1,200 @@
+use crate::world::World;
+use nalgebra::Point2;
+
+/// Uniform grid over the world bounds used to answer radius queries without scanning
+/// every item. Cells wrap around the world edges when the world does, and items outside
+/// of the bounds are kept in the closest edge cell otherwise.
+#[derive(Debug)]
+pub struct Grid {
+    origin: Point2<f64>,
+    cell_width: f64,
+    cell_height: f64,
+    columns: usize,
+    rows: usize,
+    wraps: bool,
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    /// Cells are stretched so that they evenly divide the bounds, which keeps wrapping exact
+    pub fn new(world: &World, cell_size: f64) -> Self {
+        let columns = ((world.width() / cell_size) as usize).max(1);
+        let rows = ((world.height() / cell_size) as usize).max(1);
+
+        Self {
+            origin: Point2::new(world.x.0, world.y.0),
+            cell_width: world.width() / columns as f64,
+            cell_height: world.height() / rows as f64,
+            columns,
+            rows,
+            wraps: world.wraps(),
+            cells: vec![Vec::new(); columns * rows],
+        }
+    }
+
+    pub fn from_positions(
+        positions: impl Iterator<Item = Point2<f64>>,
+        world: &World,
+        cell_size: f64,
+    ) -> Self {
+        let mut grid = Self::new(world, cell_size);
+        for (i, p) in positions.enumerate() {
+            grid.insert(i, p);
+        }
+        grid
+    }
+
+    pub fn insert(&mut self, idx: usize, position: Point2<f64>) {
+        let cell = self.cell(position);
+        self.cells[cell].push(idx);
+    }
+
+    /// Moves an item to the cell of its new position if it changed
+    pub fn relocate(&mut self, idx: usize, from: Point2<f64>, to: Point2<f64>) {
+        let (old, new) = (self.cell(from), self.cell(to));
+        if old != new {
+            if let Some(i) = self.cells[old].iter().position(|&j| j == idx) {
+                self.cells[old].swap_remove(i);
+            }
+            self.cells[new].push(idx);
+        }
+    }
+
+    /// Collects the indices of every item that could be closer than `radius` to `position`,
+    /// sorted in ascending order so callers see them in the same order as a linear scan
+    pub fn query(&self, position: Point2<f64>, radius: f64, buf: &mut Vec<usize>) {
+        buf.clear();
+
+        let columns = span(
+            position.x - self.origin.x,
+            radius,
+            self.cell_width,
+            self.columns,
+            self.wraps,
+        );
+        let rows = span(
+            position.y - self.origin.y,
+            radius,
+            self.cell_height,
+            self.rows,
+            self.wraps,
+        );
+        for row in rows {
+            for column in columns.clone() {
+                buf.extend_from_slice(&self.cells[row * self.columns + column]);
+            }
+        }
+
+        buf.sort_unstable();
+    }
+
+    fn cell(&self, position: Point2<f64>) -> usize {
+        let column = index(position.x - self.origin.x, self.cell_width, self.columns);
+        let row = index(position.y - self.origin.y, self.cell_height, self.rows);
+        row * self.columns + column
+    }
+}
+
+fn index(offset: f64, cell_size: f64, count: usize) -> usize {
+    ((offset / cell_size).floor().max(0.0) as usize).min(count - 1)
+}
+
+/// Range of cells covering `[offset - radius, offset + radius]`, either wrapped or clamped
+fn span(
+    offset: f64,
+    radius: f64,
+    cell_size: f64,
+    count: usize,
+    wraps: bool,
+) -> impl Iterator<Item = usize> + Clone {
+    let last = count as isize - 1;
+    let mut start = ((offset - radius) / cell_size).floor() as isize;
+    let mut end = ((offset + radius) / cell_size).floor() as isize;
+
+    if end - start >= last {
+        start = 0;
+        end = last;
+    } else if !wraps {
+        start = start.max(0).min(last);
+        end = end.max(0).min(last);
+    }
+    (start..=end).map(move |i| i.rem_euclid(count as isize) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Topology;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg64Mcg;
+
+    fn random_position(world: &World, margin: f64, rng: &mut impl Rng) -> Point2<f64> {
+        Point2::new(
+            rng.gen_range(world.x.0 - margin, world.x.1 + margin),
+            rng.gen_range(world.y.0 - margin, world.y.1 + margin),
+        )
+    }
+
+    /// Items closer than `radius` to `position`, found by the grid then by scanning all of them
+    fn within(
+        grid: &Grid,
+        positions: &[Point2<f64>],
+        position: Point2<f64>,
+        radius: f64,
+        world: &World,
+    ) -> (Vec<usize>, Vec<usize>) {
+        let mut found = Vec::new();
+        grid.query(position, radius, &mut found);
+        found.retain(|&i| world.distance(position, positions[i]) < radius);
+
+        let expected = (0..positions.len())
+            .filter(|&i| world.distance(position, positions[i]) < radius)
+            .collect();
+        (found, expected)
+    }
+
+    /// Compares queries of random radii with a linear scan, before and after moving the items,
+    /// with items up to `margin` outside of the bounds
+    fn check(topology: Topology, margin: f64) {
+        let world = World {
+            x: (0.0, 1000.0),
+            y: (-300.0, 300.0),
+            topology,
+        };
+        let mut rng = Pcg64Mcg::seed_from_u64(1);
+        let mut positions: Vec<Point2<f64>> = (0..500)
+            .map(|_| random_position(&world, margin, &mut rng))
+            .collect();
+        let mut grid = Grid::from_positions(positions.iter().copied(), &world, 50.0);
+
+        for round in 0..2 {
+            for _ in 0..200 {
+                let position = random_position(&world, margin, &mut rng);
+                let radius = rng.gen_range(0.0, 400.0);
+                let (found, expected) = within(&grid, &positions, position, radius, &world);
+                assert_eq!(found, expected, "{:?} round {}", topology, round);
+            }
+
+            for (i, position) in positions.iter_mut().enumerate() {
+                let to = random_position(&world, margin, &mut rng);
+                grid.relocate(i, *position, to);
+                *position = to;
+            }
+        }
+    }
+
+    #[test]
+    fn matches_scan_when_wrapped() {
+        check(Topology::Torus, 0.0);
+    }
+
+    #[test]
+    fn matches_scan_within_bounds() {
+        check(Topology::Bounce, 0.0);
+    }
+
+    #[test]
+    fn matches_scan_outside_of_bounds() {
+        check(Topology::Open, 200.0);
+    }
+}
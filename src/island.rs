@@ -0,0 +1,352 @@
+use crate::{
+    creature::{Creature, Death, LivingCreature},
+    food::{Food, FoodMap},
+    lineage::Genealogy,
+    mutation::MutationParams,
+    reproduction::{self, Evolution, Mating},
+    round,
+    stats::{Meals, MutationStats, Stats},
+    world::World,
+    Opts, BOUNDS,
+};
+use anyhow::{anyhow, Error};
+use clap::ArgEnum;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{convert::TryFrom, mem, str::FromStr};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MigrationTopology {
+    /// Every island sends migrants to the next one, the last one sending them to the first
+    Ring,
+    /// Every island sends migrants to every other island
+    Full,
+}
+
+/// Options that differ on an island, written as `food_count=50,topology=bounce`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Overrides(Map<String, Value>);
+
+/// Population living in its own world, with its own options, food and statistics
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Island {
+    opts: Opts,
+    world: World,
+
+    creatures: Vec<LivingCreature>,
+    food: Vec<Food>,
+    food_map: FoodMap,
+    /// Generator of what happens during a generation, separate from the simulation's so that
+    /// islands don't depend on the order in which they are stepped
+    rng: Pcg64Mcg,
+
+    tick: usize,
+    /// Whether the current generation ended on the island, which then waits for the others
+    ended: bool,
+    stats: Vec<Stats>,
+    /// Mutation parameters of the creatures that don't carry their own
+    mutation: MutationParams,
+    /// Mutations of the creatures born since the last sample in steady-state mode, along with
+    /// the parameters used for each of them
+    births: MutationStats,
+    birth_params: Vec<MutationParams>,
+}
+
+impl Overrides {
+    pub fn values(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.0.iter()
+    }
+}
+
+impl FromStr for Overrides {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = Map::new();
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (key, value) = match pair.find('=') {
+                Some(i) => (pair[..i].trim(), pair[i + 1..].trim()),
+                None => {
+                    return Err(anyhow!(
+                        "surcharge invalide `{}`, format attendu : option=valeur",
+                        pair
+                    ))
+                }
+            };
+            let value =
+                serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+            values.insert(key.replace('-', "_"), value);
+        }
+        Ok(Self(values))
+    }
+}
+
+impl TryFrom<String> for Overrides {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Overrides> for String {
+    fn from(overrides: Overrides) -> Self {
+        overrides
+            .0
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(s) => format!("{}={}", key, s),
+                value => format!("{}={}", key, value),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Island {
+    pub fn new(opts: Opts, seed: u64) -> Self {
+        let world = World {
+            x: BOUNDS.0,
+            y: BOUNDS.1,
+            topology: opts.topology,
+        };
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
+        Self {
+            world,
+
+            creatures: Vec::with_capacity(opts.creature_count),
+            food: Vec::with_capacity(opts.food_count),
+            food_map: FoodMap::new(&world, &opts, &mut rng),
+            rng,
+
+            tick: 0,
+            ended: false,
+            stats: Vec::new(),
+            mutation: MutationParams::new(&opts),
+            births: MutationStats::default(),
+            birth_params: Vec::new(),
+
+            opts,
+        }
+    }
+
+    pub fn opts(&self) -> &Opts {
+        &self.opts
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn creatures(&self) -> &[LivingCreature] {
+        &self.creatures
+    }
+
+    pub fn food(&self) -> &[Food] {
+        &self.food
+    }
+
+    pub fn food_map(&self) -> &FoodMap {
+        &self.food_map
+    }
+
+    /// Ticks elapsed since the start of the current generation on the island, or since the start
+    /// of the simulation in steady-state mode
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    /// Statistics of every completed generation, or of every sample, on the island
+    pub fn stats(&self) -> &[Stats] {
+        &self.stats
+    }
+
+    /// Mutation parameters of the creatures that don't carry their own
+    pub fn mutation(&self) -> MutationParams {
+        self.mutation
+    }
+
+    /// Advances the island by up to `ticks` ticks and returns whether the generation ended
+    pub(crate) fn step_ticks(&mut self, ticks: usize) -> bool {
+        // Food can only grow back between ticks
+        let (batches, ticks) = if self.opts.food_regrowth > 0.0 {
+            (ticks, 1)
+        } else {
+            (1, ticks)
+        };
+        // Generations never end in steady-state mode, unless every creature died
+        let threshold = match self.opts.evolution {
+            Evolution::Generational => self.opts.generation_threshold,
+            Evolution::SteadyState => 0,
+        };
+
+        for _ in 0..batches {
+            if self.ended {
+                break;
+            }
+            self.ended = round::run(
+                &mut self.creatures,
+                &mut self.food,
+                ticks,
+                threshold,
+                &mut self.tick,
+                &self.world,
+                &self.opts,
+            );
+            if !self.ended && self.opts.food_regrowth > 0.0 {
+                self.food_map
+                    .regrow(&mut self.food, &self.world, &self.opts, &mut self.rng);
+            }
+        }
+        self.ended
+    }
+
+    /// Removes the creatures of the generation that just ended, updating the mutation
+    /// parameters according to the schedule
+    pub(crate) fn drain(&mut self, generation: usize) -> Vec<LivingCreature> {
+        let creatures: Vec<LivingCreature> = self.creatures.drain(..).collect();
+        self.mutation.schedule(
+            creatures.iter().map(|c| &c.creature),
+            generation,
+            &self.opts,
+        );
+        creatures
+    }
+
+    /// Breeds a child next to every creature with enough energy in steady-state mode, taking its
+    /// energy from its parents
+    pub(crate) fn reproduce(&mut self, genealogy: &mut Genealogy) {
+        let cost = self.opts.reproduction_cost;
+        for (i, j) in reproduction::parents(&self.creatures, &self.world, &self.opts) {
+            let (parent_1, parent_2) = (&self.creatures[i].creature, &self.creatures[j].creature);
+            let mut child = match self.opts.mating {
+                Mating::Asexual => Creature::bud(parent_1, genealogy, &self.opts),
+                Mating::Sexual => {
+                    Creature::breed(parent_1, parent_2, genealogy, &self.opts, &mut self.rng)
+                }
+            };
+            self.birth_params.push(child.mutate_newborn(
+                self.mutation,
+                genealogy.generation,
+                &mut self.births,
+                &self.opts,
+                &mut self.rng,
+            ));
+            child.energy = cost;
+
+            if i == j {
+                self.creatures[i].creature.energy -= cost;
+            } else {
+                self.creatures[i].creature.energy -= cost / 2.0;
+                self.creatures[j].creature.energy -= cost / 2.0;
+            }
+            let position = self.creatures[i].position;
+            self.creatures
+                .push(LivingCreature::new(child, position, &mut self.rng));
+        }
+    }
+
+    /// Removes the creatures that died since the last sample in steady-state mode, updating the
+    /// mutation parameters according to the schedule, and returns their deaths along with the
+    /// food eaten and the mutations since then
+    pub(crate) fn sample(&mut self, generation: usize) -> (Vec<Death>, Meals, MutationStats) {
+        self.mutation.schedule(
+            self.creatures.iter().map(|c| &c.creature),
+            generation,
+            &self.opts,
+        );
+
+        let deaths = self
+            .creatures
+            .iter()
+            .flat_map(LivingCreature::deaths)
+            .collect();
+        let meals = Meals::collect(&self.creatures);
+        self.creatures.retain(|c| c.death.is_none());
+        for c in self.creatures.iter_mut() {
+            c.meals.iter_mut().for_each(|m| *m = 0);
+            c.scavenged.clear();
+        }
+
+        let mut births = mem::take(&mut self.births);
+        births.params(&self.birth_params);
+        self.birth_params.clear();
+        (deaths, meals, births)
+    }
+
+    /// Creatures alive on the island, in steady-state mode once the dead were removed
+    pub(crate) fn creatures_mut(&mut self) -> impl Iterator<Item = &mut Creature> {
+        self.creatures.iter_mut().map(|c| &mut c.creature)
+    }
+
+    /// Removes up to `count` random living creatures in steady-state mode
+    pub(crate) fn emigrate(&mut self, count: usize, rng: &mut impl Rng) -> Vec<Creature> {
+        let mut migrants = Vec::with_capacity(count);
+        for _ in 0..count.min(self.creatures.len()) {
+            let i = rng.gen_range(0, self.creatures.len());
+            migrants.push(self.creatures.swap_remove(i).creature);
+        }
+        migrants
+    }
+
+    /// Places creatures coming from other islands at random positions in steady-state mode
+    pub(crate) fn immigrate(&mut self, creatures: Vec<Creature>, rng: &mut impl Rng) {
+        if !creatures.is_empty() {
+            self.ended = false;
+        }
+        for c in creatures {
+            self.creatures
+                .push(LivingCreature::random(c, self.world.x, self.world.y, rng));
+        }
+    }
+
+    pub(crate) fn push_stats(&mut self, stats: Stats) {
+        self.stats.push(stats);
+    }
+
+    /// Places the creatures of the next generation along with fresh food
+    pub(crate) fn spawn(&mut self, creatures: Vec<Creature>, rng: &mut impl Rng) {
+        self.tick = 0;
+        self.ended = false;
+        round::position_creatures(creatures, &mut self.creatures, &self.world, rng);
+        self.food_map.spawn(
+            &mut self.food,
+            self.opts.food_count,
+            &self.world,
+            &self.opts,
+            rng,
+        );
+    }
+}
+
+/// Moves random creatures from every island to its neighbours, so that every island keeps as
+/// many creatures as it had if none of them is empty
+pub fn migrate(populations: &mut [Vec<Creature>], opts: &Opts, rng: &mut impl Rng) {
+    let count = populations.len();
+    let mut arrivals = vec![Vec::new(); count];
+
+    for (i, population) in populations.iter_mut().enumerate() {
+        for j in destinations(i, count, opts) {
+            for _ in 0..opts.migrants.min(population.len()) {
+                let k = rng.gen_range(0, population.len());
+                arrivals[j].push(population.swap_remove(k));
+            }
+        }
+    }
+
+    for (population, arrivals) in populations.iter_mut().zip(arrivals) {
+        population.extend(arrivals);
+    }
+}
+
+/// Islands that receive the migrants of island `i` out of `count`
+pub fn destinations(i: usize, count: usize, opts: &Opts) -> Vec<usize> {
+    match opts.migration_topology {
+        MigrationTopology::Ring => vec![(i + 1) % count],
+        MigrationTopology::Full => (0..count).filter(|&j| j != i).collect(),
+    }
+}
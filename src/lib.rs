@@ -0,0 +1,337 @@
+pub mod breeding;
+mod config;
+pub mod creature;
+mod display;
+pub mod food;
+pub mod genome;
+mod grid;
+pub mod island;
+pub mod lineage;
+pub mod mutation;
+pub mod reproduction;
+pub mod round;
+pub mod selection;
+mod simulation;
+pub mod speciation;
+pub mod stats;
+pub mod world;
+
+pub use crate::simulation::{Simulation, Step};
+
+use crate::{
+    breeding::{Crossover, DietInheritance},
+    creature::{Diet, DietMode},
+    food::{Bushes, FoodDistribution, FoodMix},
+    genome::{BudgetModel, Dominance, TraitWeights},
+    island::{MigrationTopology, Overrides},
+    mutation::{MutationControl, MutationOperator},
+    reproduction::{Evolution, Mating},
+    round::TickMode,
+    selection::Selection,
+    speciation::Niching,
+    world::Topology,
+};
+use clap::{
+    AppSettings::{ColoredHelp, DeriveDisplayOrder, DisableVersion, NextLineHelp},
+    Clap,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub const NAME: &str = "Projet de session - 0SW - Raphaël Thériault";
+pub const BOUNDS: ((f64, f64), (f64, f64)) = ((0.0, 1000.0), (0.0, 1000.0));
+
+/// Simulation génétique pour le cours 0SW.
+/// Espace pour pauser/reprendre,
+/// haut/bas pour controller le multiplicateur de vitesse,
+/// D pour activer/désactiver la vue détaillée,
+/// gauche/droite pour changer l'île affichée,
+/// S pour écrire une sauvegarde.
+/// Le mode --headless exécute la simulation sans fenêtre.
+#[derive(Debug, Clone, Clap, Serialize, Deserialize)]
+#[clap(
+    name = NAME,
+    setting = DeriveDisplayOrder,
+    setting = NextLineHelp,
+    setting = DisableVersion,
+    setting = ColoredHelp
+)]
+pub struct Opts {
+    /// Nombre de créatures par génération
+    #[clap(short, long, default_value = "100")]
+    pub creature_count: usize,
+
+    /// Quantité de nourriture disponible par génération
+    #[clap(short, long, default_value = "100")]
+    pub food_count: usize,
+
+    /// Quantité minimale de créatures en vie pour continuer la génération
+    #[clap(short, long, default_value = "50")]
+    pub generation_threshold: usize,
+
+    /// Multiplicateur de vitesse de départ
+    #[clap(short, long, default_value = "1")]
+    pub start_speed: usize,
+
+    /// Exécuter la simulation sans fenêtre ni limite de FPS
+    #[clap(long)]
+    pub headless: bool,
+    /// Nombre de générations, ou de relevés en mode continu, à simuler avant de quitter (illimité par défaut)
+    #[clap(long)]
+    pub generations: Option<usize>,
+
+    /// Graine du générateur aléatoire (aléatoire par défaut)
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// Fichier de configuration TOML ou JSON, les options passées explicitement ont priorité
+    #[clap(long, parse(from_os_str))]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Reprendre la simulation depuis un fichier de sauvegarde
+    #[clap(long, parse(from_os_str))]
+    pub resume: Option<PathBuf>,
+    /// Écrire une sauvegarde toutes les N générations, ou tous les N relevés en mode continu
+    #[clap(long)]
+    pub checkpoint_every: Option<usize>,
+
+    /// Topologie du monde (torus, bounce, walls ou open)
+    #[clap(arg_enum, long, default_value = "torus")]
+    pub topology: Topology,
+
+    /// Déroulement d'une itération (sequential, two-phase ou parallel)
+    #[clap(arg_enum, long, default_value = "sequential")]
+    pub tick_mode: TickMode,
+
+    /// Répartition de la nourriture (uniform, patches, fertility ou bushes)
+    #[clap(arg_enum, long, default_value = "uniform")]
+    pub food_distribution: FoodDistribution,
+    /// Nombre d'amas de nourriture, ou de buissons placés au hasard si --bushes est absent
+    #[clap(long, default_value = "5")]
+    pub food_patches: usize,
+    /// Rayon autour du centre des amas et des buissons où pousse la nourriture
+    #[clap(long, default_value = "40.0")]
+    pub patch_radius: f64,
+    /// Taille des cellules de la carte de fertilité
+    #[clap(long, default_value = "200.0")]
+    pub noise_scale: f64,
+    /// Position des buissons, par exemple 250:250,750:500
+    #[clap(long)]
+    pub bushes: Option<Bushes>,
+    /// Quantité moyenne de nourriture qui repousse à chaque itération
+    #[clap(long, default_value = "0.0")]
+    pub food_regrowth: f64,
+    /// Quantité maximale de nourriture disponible en même temps lors de la repousse, sans compter les carcasses (--food-count par défaut)
+    #[clap(long)]
+    pub food_cap: Option<usize>,
+    /// Proportion de chaque type de nourriture (plant, fruit, toxic), par exemple plant=1,toxic=0.2 (plantes seulement par défaut)
+    #[clap(long)]
+    pub food_mix: Option<FoodMix>,
+    /// Énergie donnée par une plante
+    #[clap(long, default_value = "500.0")]
+    pub plant_energy: f64,
+    /// Nombre d'itérations avant qu'une plante pourrisse (jamais par défaut)
+    #[clap(long)]
+    pub plant_lifetime: Option<usize>,
+    /// Énergie donnée par un fruit frais
+    #[clap(long, default_value = "1000.0")]
+    pub fruit_energy: f64,
+    /// Nombre d'itérations avant qu'un fruit pourrisse, son énergie diminuant jusque-là
+    #[clap(long, default_value = "300")]
+    pub fruit_lifetime: usize,
+    /// Énergie perdue en mangeant de la nourriture toxique
+    #[clap(long, default_value = "500.0")]
+    pub toxic_energy: f64,
+    /// Énergie dépensée à chaque itération par une créature qui reconnaît la nourriture toxique dans tout son champ de vision
+    #[clap(long, default_value = "0.25")]
+    pub discernment_cost: f64,
+    /// Énergie du corps d'une créature par unité de taille
+    #[clap(long, default_value = "50.0")]
+    pub body_energy: f64,
+    /// Part de l'énergie du corps laissée dans la carcasse d'une créature morte de faim
+    #[clap(long, default_value = "0.5")]
+    pub carcass_share: f64,
+    /// Nombre d'itérations avant qu'une carcasse pourrisse, son énergie diminuant jusque-là
+    #[clap(long, default_value = "500")]
+    pub carcass_lifetime: usize,
+    /// Énergie maximale mangée en une bouchée dans une carcasse
+    #[clap(long, default_value = "150.0")]
+    pub carcass_portion: f64,
+    /// Permettre l'alimentation charognarde, qui ne mange que des carcasses
+    #[clap(long)]
+    pub scavengers: bool,
+    /// Permettre l'alimentation omnivore, qui mange de tout avec une efficacité réduite
+    #[clap(long)]
+    pub omnivores: bool,
+    /// Part de l'énergie de la nourriture obtenue par les omnivores
+    #[clap(long, default_value = "0.6")]
+    pub omnivore_efficiency: f64,
+    /// Détermination de l'alimentation (categorical, ou continuous pour un gène de carnivorie entre 0 et 1)
+    #[clap(arg_enum, long, default_value = "categorical")]
+    pub diet_mode: DietMode,
+
+    /// Nombre d'îles, chacune avec son propre monde, ses créatures et sa nourriture
+    #[clap(long, default_value = "1")]
+    pub islands: usize,
+    /// Options propres à une île, par exemple food_count=50,topology=bounce, répété pour chaque île dans l'ordre
+    #[clap(long, number_of_values = 1)]
+    pub island: Vec<Overrides>,
+    /// Nombre de générations entre deux migrations
+    #[clap(long, default_value = "10")]
+    pub migration_interval: usize,
+    /// Nombre de créatures envoyées par une île à chacune de ses voisines lors d'une migration
+    #[clap(long, default_value = "5")]
+    pub migrants: usize,
+    /// Voisines de chaque île pour les migrations (ring ou full)
+    #[clap(arg_enum, long, default_value = "ring")]
+    pub migration_topology: MigrationTopology,
+
+    /// Évolution de la population (generational, ou steady-state pour des naissances et des morts continues pendant la génération)
+    #[clap(arg_enum, long, default_value = "generational")]
+    pub evolution: Evolution,
+    /// Énergie à partir de laquelle une créature se reproduit en mode continu
+    #[clap(long, default_value = "1500.0")]
+    pub reproduction_threshold: f64,
+    /// Énergie transmise à l'enfant en mode continu, prise à ses parents
+    #[clap(long, default_value = "1000.0")]
+    pub reproduction_cost: f64,
+    /// Reproduction en mode continu (asexual, ou sexual avec un partenaire dans le champ de vision)
+    #[clap(arg_enum, long, default_value = "asexual")]
+    pub mating: Mating,
+    /// Nombre d'itérations entre deux relevés des statistiques en mode continu
+    #[clap(long, default_value = "100")]
+    pub sample_interval: usize,
+
+    /// Sélection des parents (roulette, tournament, rank, truncation, stochastic-universal ou elitist)
+    #[clap(arg_enum, long, default_value = "roulette")]
+    pub selection: Selection,
+    /// Nombre de créatures qui s'affrontent dans chaque tournoi
+    #[clap(long, default_value = "3")]
+    pub tournament_size: usize,
+    /// Proportion des créatures les plus aptes parmi lesquelles choisir les parents par troncature
+    #[clap(long, default_value = "0.5")]
+    pub truncation_ratio: f64,
+    /// Nombre de créatures les plus aptes conservées par la sélection élitiste
+    #[clap(long, default_value = "10")]
+    pub elite_count: usize,
+
+    /// Préservation de la diversité par espèce (none, sharing ou quotas)
+    #[clap(arg_enum, long, default_value = "none")]
+    pub niching: Niching,
+    /// Distance génétique maximale entre une créature et le représentant de son espèce
+    #[clap(long, default_value = "0.3")]
+    pub species_threshold: f64,
+
+    /// Croisement des caractéristiques des parents (blend, uniform, gene-blend, blx-alpha, sbx ou clone)
+    #[clap(arg_enum, long, default_value = "blend")]
+    pub crossover: Crossover,
+    /// Extension de l'intervalle entre les parents pour le croisement BLX-alpha
+    #[clap(long, default_value = "0.5")]
+    pub blx_alpha: f64,
+    /// Indice de distribution du croisement SBX, les enfants sont plus proches des parents quand il est élevé
+    #[clap(long, default_value = "2.0")]
+    pub sbx_eta: f64,
+    /// Héritage de l'alimentation (dominant, random ou fitter)
+    #[clap(arg_enum, long, default_value = "dominant")]
+    pub diet_inheritance: DietInheritance,
+
+    /// Donner deux allèles à chaque gène, l'enfant recevant un allèle de chaque parent
+    #[clap(long)]
+    pub diploid: bool,
+    /// Expression des caractéristiques à partir de leurs deux allèles (additive, dominant ou recessive)
+    #[clap(arg_enum, long, default_value = "additive")]
+    pub dominance: Dominance,
+    /// Alimentation dominante, les autres n'étant exprimées que si les deux allèles les portent (herbivore, carnivore, scavenger ou omnivore)
+    #[clap(arg_enum, long, default_value = "herbivore")]
+    pub dominant_diet: Diet,
+
+    /// Probability de mutation
+    #[clap(long, default_value = "0.05")]
+    pub mutation_probability: f64,
+
+    /// Ampleur des mutation
+    #[clap(long, default_value = "1.0")]
+    pub mutation_scale: f64,
+    /// Opérateur de mutation (uniform, symmetric, gaussian ou creep)
+    #[clap(arg_enum, long, default_value = "uniform")]
+    pub mutation_operator: MutationOperator,
+    /// Écart type des mutations gaussiennes
+    #[clap(long, default_value = "0.1")]
+    pub mutation_sigma: f64,
+    /// Probabilité de mutation de chaque gène d'une créature qui mute
+    #[clap(long, default_value = "1.0")]
+    pub gene_mutation_probability: f64,
+    /// Probabilité qu'une mutation creep remplace le gène par une nouvelle valeur aléatoire
+    #[clap(long, default_value = "0.1")]
+    pub mutation_reset_probability: f64,
+    /// Contrôle de la probabilité et de l'ampleur des mutations (fixed, self-adaptive, one-fifth, linear-decay ou exponential-decay)
+    #[clap(arg_enum, long, default_value = "fixed")]
+    pub mutation_control: MutationControl,
+    /// Taux d'apprentissage des paramètres de mutation propres à chaque créature
+    #[clap(long, default_value = "0.2")]
+    pub mutation_learning_rate: f64,
+    /// Facteur appliqué à l'ampleur des mutations par la règle du 1/5
+    #[clap(long, default_value = "0.85")]
+    pub one_fifth_factor: f64,
+    /// Nombre de générations pour atteindre le plancher avec la décroissance linéaire
+    #[clap(long, default_value = "100")]
+    pub mutation_decay_generations: usize,
+    /// Facteur appliqué à chaque génération par la décroissance exponentielle
+    #[clap(long, default_value = "0.99")]
+    pub mutation_decay: f64,
+    /// Proportion des paramètres de mutation de départ en deçà de laquelle ils ne décroissent plus
+    #[clap(long, default_value = "0.1")]
+    pub mutation_floor: f64,
+
+    /// Modèle de budget des caractéristiques (linear, weighted, power, soft ou none)
+    #[clap(arg_enum, long, default_value = "linear")]
+    pub budget: BudgetModel,
+    /// Coût total que les caractéristiques ne peuvent pas dépasser
+    #[clap(long, default_value = "2.0")]
+    pub budget_total: f64,
+    /// Coût de chaque caractéristique pour les modèles pondérés, par exemple speed=1.5,size=2
+    #[clap(long)]
+    pub trait_weights: Option<TraitWeights>,
+    /// Exposant appliqué aux caractéristiques par le modèle power
+    #[clap(long, default_value = "2.0")]
+    pub budget_exponent: f64,
+    /// Énergie dépensée à chaque itération par unité de coût au-delà du total avec le modèle soft
+    #[clap(long, default_value = "1.0")]
+    pub budget_penalty: f64,
+
+    /// Énergie maximale
+    #[clap(long, default_value = "2000.0")]
+    pub max_energy: f64,
+    /// Énergie de départ
+    #[clap(long, default_value = "1000.0")]
+    pub start_energy: f64,
+
+    /// Vitesse de base pour toutes les créatures
+    #[clap(long, default_value = "1.33")]
+    pub base_speed: f64,
+    /// Multiplicateur appliqué à la vitesse individuelle de chaque créature
+    #[clap(long, default_value = "0.67")]
+    pub speed_factor: f64,
+    /// Multiplicateur appliqué quand une créature ne voit ni nourriture ni prédateurs
+    #[clap(long, default_value = "0.75")]
+    pub normal_speed: f64,
+
+    /// Multiplicateur appliqué à l'endurance individuelle de chaque créature
+    #[clap(long, default_value = "1.0")]
+    pub stamina_factor: f64,
+
+    /// Champ de vision de base pour toutes les créatures
+    #[clap(long, default_value = "45.0")]
+    pub base_fov: f64,
+    /// Multiplicateur appliqué au champ de vision individuel de chaque créature
+    #[clap(long, default_value = "55.0")]
+    pub fov_factor: f64,
+
+    /// Taille de base pour toutes les créatures
+    #[clap(long, default_value = "5.0")]
+    pub base_size: f64,
+    /// Multiplicateur appliqué à la taille individuelle de chaque créature
+    #[clap(long, default_value = "5.0")]
+    pub size_factor: f64,
+}
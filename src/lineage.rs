@@ -0,0 +1,216 @@
+use crate::creature::{Creature, Diet};
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Identity and origins of a creature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ancestry {
+    pub id: u64,
+    /// Ids of the parents, or `None` for a founder
+    pub parents: Option<[u64; 2]>,
+    /// Generation during which the creature was born
+    pub born: usize,
+    pub mutations: Vec<Mutation>,
+    /// Founders the creature descends from, as a bitset of their founder index
+    founders: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mutation {
+    /// Generation during which the creature lived with the mutated genes for the first time
+    pub generation: usize,
+    /// Indices of the mutated genes
+    pub genes: Vec<usize>,
+}
+
+/// Every creature that ever lived in a simulation, indexed by id
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Genealogy {
+    /// Generation during which newly born creatures will live
+    pub generation: usize,
+    records: Vec<Record>,
+    founders: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    parents: Option<[u64; 2]>,
+    born: usize,
+    /// Last generation during which the creature was alive
+    last: usize,
+    diet: Diet,
+    mutations: usize,
+}
+
+impl Genealogy {
+    pub fn new() -> Self {
+        Self {
+            generation: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Registers a new creature, which is a founder if it has no parents
+    pub fn birth(&mut self, parents: Option<[&Creature; 2]>, diet: Diet) -> Ancestry {
+        let id = self.records.len() as u64;
+        let founders = match parents {
+            Some([p1, p2]) => {
+                let (p1, p2) = (&p1.ancestry.founders, &p2.ancestry.founders);
+                (0..p1.len().max(p2.len()))
+                    .map(|i| p1.get(i).unwrap_or(&0) | p2.get(i).unwrap_or(&0))
+                    .collect()
+            }
+            None => {
+                let mut founders = vec![0; self.founders / 64 + 1];
+                founders[self.founders / 64] |= 1 << (self.founders % 64);
+                self.founders += 1;
+                founders
+            }
+        };
+        let parents = parents.map(|[p1, p2]| [p1.ancestry.id, p2.ancestry.id]);
+
+        self.records.push(Record {
+            parents,
+            born: self.generation,
+            last: self.generation,
+            diet,
+            mutations: 0,
+        });
+        Ancestry {
+            id,
+            parents,
+            born: self.generation,
+            mutations: Vec::new(),
+            founders,
+        }
+    }
+
+    /// Records that the creatures are alive during the current generation
+    pub fn update(&mut self, creatures: &[Creature]) {
+        for c in creatures {
+            let record = &mut self.records[c.ancestry.id as usize];
+            record.last = self.generation;
+            record.diet = c.diet();
+            record.mutations = c.ancestry.mutations.len();
+        }
+    }
+
+    /// Number of founders that still have descendants among the creatures
+    pub fn surviving_founders(creatures: &[Creature]) -> usize {
+        let mut founders: Vec<u64> = Vec::new();
+        for c in creatures {
+            let bits = &c.ancestry.founders;
+            if founders.len() < bits.len() {
+                founders.resize(bits.len(), 0);
+            }
+            for (f, b) in founders.iter_mut().zip(bits) {
+                *f |= b;
+            }
+        }
+        founders.iter().map(|f| f.count_ones() as usize).sum()
+    }
+
+    /// Writes the genealogy as a Newick tree and a GraphML graph next to the given path
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path.with_extension("nwk"), self.newick())?;
+        self.write_graphml(&path.with_extension("graphml"))
+    }
+
+    /// Newick can only describe trees, so every creature hangs from its first parent, under a
+    /// root holding the founders, and branch lengths are in generations
+    fn newick(&self) -> String {
+        let mut children = vec![Vec::new(); self.records.len()];
+        let mut roots = Vec::new();
+        for (id, r) in self.records.iter().enumerate() {
+            match r.parents {
+                Some([parent, _]) => children[parent as usize].push(id),
+                None => roots.push(id),
+            }
+        }
+
+        let mut newick = String::from("(");
+        for (i, &root) in roots.iter().enumerate() {
+            if i > 0 {
+                newick.push(',');
+            }
+
+            // Iterative post-order traversal, since trees can be as deep as the run is long
+            let mut stack = vec![(root, 0)];
+            while let Some((id, next)) = stack.pop() {
+                if next < children[id].len() {
+                    newick.push(if next == 0 { '(' } else { ',' });
+                    stack.push((id, next + 1));
+                    stack.push((children[id][next], 0));
+                } else {
+                    if !children[id].is_empty() {
+                        newick.push(')');
+                    }
+                    let length = match self.records[id].parents {
+                        Some([parent, _]) => {
+                            self.records[id].born - self.records[parent as usize].born
+                        }
+                        None => 0,
+                    };
+                    write!(newick, "{}:{}", id, length).unwrap();
+                }
+            }
+        }
+        newick.push_str(");\n");
+        newick
+    }
+
+    fn write_graphml(&self, path: &Path) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        for (key, kind) in &[
+            ("born", "int"),
+            ("last", "int"),
+            ("diet", "string"),
+            ("mutations", "int"),
+        ] {
+            writeln!(
+                writer,
+                r#"  <key id="{0}" for="node" attr.name="{0}" attr.type="{1}"/>"#,
+                key, kind
+            )?;
+        }
+        writeln!(writer, r#"  <graph id="genealogy" edgedefault="directed">"#)?;
+
+        for (id, r) in self.records.iter().enumerate() {
+            writeln!(writer, r#"    <node id="{}">"#, id)?;
+            writeln!(writer, r#"      <data key="born">{}</data>"#, r.born)?;
+            writeln!(writer, r#"      <data key="last">{}</data>"#, r.last)?;
+            writeln!(writer, r#"      <data key="diet">{:?}</data>"#, r.diet)?;
+            writeln!(
+                writer,
+                r#"      <data key="mutations">{}</data>"#,
+                r.mutations
+            )?;
+            writeln!(writer, "    </node>")?;
+        }
+        for (id, r) in self.records.iter().enumerate() {
+            if let Some([p1, p2]) = r.parents {
+                writeln!(writer, r#"    <edge source="{}" target="{}"/>"#, p1, id)?;
+                if p2 != p1 {
+                    writeln!(writer, r#"    <edge source="{}" target="{}"/>"#, p2, id)?;
+                }
+            }
+        }
+
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -1,104 +1,112 @@
-mod breeding;
-mod creature;
-mod display;
-mod food;
-mod mutation;
-mod round;
-mod selection;
-mod stats;
 mod ui;
 
-use crate::{creature::Creature, stats::Stats};
 use anyhow::Error;
-use clap::{
-    AppSettings::{ColoredHelp, DeriveDisplayOrder, DisableVersion, NextLineHelp},
-    Clap,
-};
-use rand::prelude::{Rng, SeedableRng, SmallRng};
 use sdl2::{event::Event, keyboard::Keycode, pixels::Color};
+use session_0sw::{
+    reproduction::Evolution,
+    stats::{self, Stats},
+    Opts, Simulation, Step, NAME,
+};
 use std::{
     thread,
     time::{Duration, Instant},
 };
 
-const NAME: &str = "Projet de session - 0SW - Raphaël Thériault";
-
-/// Simulation génétique pour le cours 0SW.
-/// Espace pour pauser/reprendre,
-/// haut/bas pour controller le multiplicateur de vitesse,
-/// D pour activer/désactiver la vue détaillée.
-#[derive(Debug, Clap)]
-#[clap(
-    name = NAME,
-    setting = DeriveDisplayOrder,
-    setting = NextLineHelp,
-    setting = DisableVersion,
-    setting = ColoredHelp
-)]
-pub struct Opts {
-    /// Nombre de créatures par génération
-    #[clap(short, long, default_value = "100")]
-    creature_count: usize,
-
-    /// Quantité de nourriture disponible par génération
-    #[clap(short, long, default_value = "100")]
-    food_count: usize,
-
-    /// Quantité minimale de créatures en vie pour continuer la génération
-    #[clap(short, long, default_value = "50")]
-    generation_threshold: usize,
-
-    /// Multiplicateur de vitesse de départ
-    #[clap(short, long, default_value = "1")]
-    start_speed: usize,
-
-    /// Probability de mutation
-    #[clap(long, default_value = "0.05")]
-    mutation_probability: f64,
-
-    /// Ampleur des mutation
-    #[clap(long, default_value = "1.0")]
-    mutation_scale: f64,
-
-    /// Énergie maximale
-    #[clap(long, default_value = "2000.0")]
-    pub max_energy: f64,
-    /// Énergie de départ
-    #[clap(long, default_value = "1000.0")]
-    pub start_energy: f64,
-
-    /// Vitesse de base pour toutes les créatures
-    #[clap(long, default_value = "1.33")]
-    pub base_speed: f64,
-    /// Multiplicateur appliqué à la vitesse individuelle de chaque créature
-    #[clap(long, default_value = "0.67")]
-    pub speed_factor: f64,
-    /// Multiplicateur appliqué quand une créature ne voit ni nourriture ni prédateurs
-    #[clap(long, default_value = "0.75")]
-    pub normal_speed: f64,
-
-    /// Multiplicateur appliqué à l'endurance individuelle de chaque créature
-    #[clap(long, default_value = "1.0")]
-    pub stamina_factor: f64,
-
-    /// Champ de vision de base pour toutes les créatures
-    #[clap(long, default_value = "45.0")]
-    pub base_fov: f64,
-    /// Multiplicateur appliqué au champ de vision individuel de chaque créature
-    #[clap(long, default_value = "55.0")]
-    pub fov_factor: f64,
-
-    /// Taille de base pour toutes les créatures
-    #[clap(long, default_value = "5.0")]
-    pub base_size: f64,
-    /// Multiplicateur appliqué à la taille individuelle de chaque créature
-    #[clap(long, default_value = "5.0")]
-    pub size_factor: f64,
+fn main() -> Result<(), Error> {
+    let opts = Opts::load()?;
+    if opts.headless {
+        headless(&opts)
+    } else {
+        window(&opts)
+    }
 }
 
-fn main() -> Result<(), Error> {
-    let opts: Opts = Opts::parse();
+/// Crée la simulation ou la reprend depuis une sauvegarde, et affiche sa graine
+fn simulation(opts: &Opts) -> Result<Simulation, Error> {
+    let simulation = match &opts.resume {
+        Some(path) => Simulation::load(path)?,
+        None => Simulation::new(opts.clone())?,
+    };
+    println!("Graine {}", simulation.seed());
+    Ok(simulation)
+}
+
+/// Génération donnée, ou itération en cours en mode continu
+fn progress(simulation: &Simulation, generation: usize) -> String {
+    match simulation.opts().evolution {
+        Evolution::Generational => format!("Génération {}", generation),
+        Evolution::SteadyState => format!("Itération {}", simulation.tick()),
+    }
+}
+
+/// Écrit une sauvegarde si la génération qui vient de se terminer correspond à l'intervalle
+fn checkpoint(simulation: &Simulation, opts: &Opts) -> Result<(), Error> {
+    if let Some(n) = opts.checkpoint_every {
+        if (simulation.generation() - 1).is_multiple_of(n) {
+            let path = simulation.checkpoint()?;
+            println!("Sauvegarde écrite dans {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Écrit les graphiques, les données et la généalogie de la simulation, ainsi que les
+/// graphiques et les données de chaque île s'il y en a plusieurs
+fn write(simulation: &Simulation) -> Result<(), Error> {
+    let seed = simulation.seed();
+    let path = stats::path(seed)?;
+    Stats::write(
+        simulation.stats(),
+        &path,
+        &format!("Graine {}", seed),
+        simulation.opts(),
+    )?;
+
+    let islands = simulation.islands();
+    if islands.len() > 1 {
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        for (i, island) in islands.iter().enumerate() {
+            Stats::write(
+                island.stats(),
+                &path.with_file_name(format!("{}-ile-{}.svg", stem, i + 1)),
+                &format!("Graine {} - île {}", seed, i + 1),
+                island.opts(),
+            )?;
+        }
+    }
+
+    simulation.genealogy().write(&path)
+}
+
+fn headless(opts: &Opts) -> Result<(), Error> {
+    let mut simulation = simulation(opts)?;
+
+    loop {
+        let generation = simulation.generation();
+        let step = simulation.step_generation();
+        let progress = progress(&simulation, generation);
+        if step == Step::Extinct {
+            println!("{} - extinction", progress);
+            break;
+        }
+        println!("{} - {}", progress, simulation.stats().last().unwrap());
+        let islands = simulation.islands();
+        if islands.len() > 1 {
+            for (i, island) in islands.iter().enumerate() {
+                println!("    Île {} - {}", i + 1, island.stats().last().unwrap());
+            }
+        }
+        checkpoint(&simulation, opts)?;
+
+        if matches!(opts.generations, Some(g) if simulation.generation() > g) {
+            break;
+        }
+    }
+
+    write(&simulation)
+}
 
+fn window(opts: &Opts) -> Result<(), Error> {
     let sdl = sdl2::init().map_err(Error::msg)?;
     let video_subsystem = sdl.video().map_err(Error::msg)?;
     let window = video_subsystem
@@ -119,138 +127,151 @@ fn main() -> Result<(), Error> {
     canvas.clear();
     canvas.present();
 
-    let mut rng = SmallRng::from_entropy();
-    let (x, y) = ((0.0, 1000.0), (0.0, 1000.0));
-
-    let mut creatures: Vec<Creature> = (0..opts.creature_count)
-        .map(|_| Creature::random(&opts, &mut rng))
-        .collect();
+    let mut simulation = simulation(opts)?;
 
-    let mut generation = 1;
     let mut paused = false;
     let mut debug = false;
     let mut speed = opts.start_speed;
-
-    let mut text = ui::render(generation, paused, speed, debug, &font, &tc)?;
+    let mut shown = 0;
+    let islands = simulation.islands().len();
+
+    let mut text = ui::render(
+        &progress(&simulation, simulation.generation()),
+        (shown, islands),
+        paused,
+        speed,
+        debug,
+        &font,
+        &tc,
+    )?;
     let mut ui_needs_refresh = true;
 
-    let mut stats = Vec::new();
+    let mut last_frame = Instant::now();
+    let mut delta_time;
 
     'main: loop {
-        let mut living_creatures = Vec::with_capacity(creatures.len());
-        crate::round::position_creatures(&mut creatures, &mut living_creatures, x, y, &mut rng);
-
-        let mut food = Vec::with_capacity(opts.food_count);
-        crate::round::generate_food(&mut food, opts.food_count, x, y, &mut rng);
-
-        let mut last_frame = Instant::now();
-        let mut delta_time;
-
-        loop {
-            for event in events.poll_iter() {
-                match event {
-                    Event::Quit { .. } => break 'main,
-
-                    Event::KeyUp {
-                        keycode: Some(Keycode::Space),
-                        ..
-                    } => {
-                        paused = !paused;
-                        ui_needs_refresh = true;
-                    }
-
-                    Event::KeyUp {
-                        keycode: Some(Keycode::D),
-                        ..
-                    } => {
-                        debug = !debug;
-                        ui_needs_refresh = true;
-                    }
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'main,
+
+                Event::KeyUp {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    paused = !paused;
+                    ui_needs_refresh = true;
+                }
 
-                    Event::KeyUp {
-                        keycode: Some(Keycode::Up),
-                        ..
-                    } if speed < 256 => {
-                        speed += 1;
-                        ui_needs_refresh = true;
-                    }
-                    Event::KeyUp {
-                        keycode: Some(Keycode::Down),
-                        ..
-                    } if speed > 1 => {
-                        speed -= 1;
-                        ui_needs_refresh = true;
-                    }
+                Event::KeyUp {
+                    keycode: Some(Keycode::D),
+                    ..
+                } => {
+                    debug = !debug;
+                    ui_needs_refresh = true;
+                }
 
-                    _ => (),
+                Event::KeyUp {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => {
+                    let path = simulation.checkpoint()?;
+                    println!("Sauvegarde écrite dans {}", path.display());
                 }
-            }
 
-            if !paused
-                && crate::round::run(
-                    &mut living_creatures,
-                    &mut food,
-                    speed,
-                    opts.generation_threshold,
-                    x,
-                    y,
-                    &opts,
-                )
-            {
-                break;
-            }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Up),
+                    ..
+                } if speed < 256 => {
+                    speed += 1;
+                    ui_needs_refresh = true;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Down),
+                    ..
+                } if speed > 1 => {
+                    speed -= 1;
+                    ui_needs_refresh = true;
+                }
 
-            canvas.set_draw_color(Color::RGB(255, 255, 255));
-            canvas.clear();
+                Event::KeyUp {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } => {
+                    shown = (shown + 1) % islands;
+                    ui_needs_refresh = true;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Left),
+                    ..
+                } => {
+                    shown = (shown + islands - 1) % islands;
+                    ui_needs_refresh = true;
+                }
 
-            for f in &food {
-                f.draw(&canvas)?;
-            }
-            for c in &living_creatures {
-                c.draw(&canvas, debug, &opts)?;
+                _ => (),
             }
+        }
 
-            if ui_needs_refresh {
-                text = ui::render(generation, paused, speed, debug, &font, &tc)?;
-                ui_needs_refresh = false;
+        if !paused {
+            let steady_state = simulation.opts().evolution == Evolution::SteadyState;
+            match simulation.step_ticks(speed) {
+                // The tick is shown instead of the generation in steady-state mode
+                Step::Running => ui_needs_refresh |= steady_state,
+                Step::Generation | Step::Sample => {
+                    checkpoint(&simulation, opts)?;
+                    if matches!(opts.generations, Some(g) if simulation.generation() > g) {
+                        break;
+                    }
+                    ui_needs_refresh = true;
+                }
+                Step::Extinct => break,
             }
-            canvas
-                .copy(&text.0 .0, None, Some(text.0 .1))
-                .map_err(Error::msg)?;
-            canvas
-                .copy(&text.1 .0, None, Some(text.1 .1))
-                .map_err(Error::msg)?;
-            canvas
-                .copy(&text.2 .0, None, Some(text.2 .1))
-                .map_err(Error::msg)?;
-
-            canvas.present();
-
-            delta_time = last_frame.elapsed();
-            last_frame = Instant::now();
-            thread::sleep(Duration::new(
-                0,
-                (1_000_000_000u32 / 60).saturating_sub(delta_time.subsec_nanos()),
-            ));
         }
 
-        creatures.retain(|c| c.energy > 0.0);
-        if creatures.is_empty() {
-            break;
-        }
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.clear();
 
-        Creature::select(&mut creatures, opts.creature_count, &opts, &mut rng);
-        for c in creatures.iter_mut() {
-            if rng.gen_bool(opts.mutation_probability) {
-                c.mutate(opts.mutation_scale, &mut rng);
-            }
-            c.energy = opts.start_energy;
+        let island = &simulation.islands()[shown];
+        let world = island.world();
+        world.draw(&canvas)?;
+        for f in island.food() {
+            f.draw(&canvas, world)?;
+        }
+        for c in island.creatures() {
+            c.draw(&canvas, debug, world, island.opts())?;
         }
 
-        stats.push(Stats::collect(&creatures));
-        generation += 1;
-        ui_needs_refresh = true;
+        if ui_needs_refresh {
+            text = ui::render(
+                &progress(&simulation, simulation.generation()),
+                (shown, islands),
+                paused,
+                speed,
+                debug,
+                &font,
+                &tc,
+            )?;
+            ui_needs_refresh = false;
+        }
+        canvas
+            .copy(&text.0 .0, None, Some(text.0 .1))
+            .map_err(Error::msg)?;
+        canvas
+            .copy(&text.1 .0, None, Some(text.1 .1))
+            .map_err(Error::msg)?;
+        canvas
+            .copy(&text.2 .0, None, Some(text.2 .1))
+            .map_err(Error::msg)?;
+
+        canvas.present();
+
+        delta_time = last_frame.elapsed();
+        last_frame = Instant::now();
+        thread::sleep(Duration::new(
+            0,
+            (1_000_000_000u32 / 60).saturating_sub(delta_time.subsec_nanos()),
+        ));
     }
 
-    Stats::write(&stats)
+    write(&simulation)
 }
@@ -1,27 +1,257 @@
-use crate::creature::{Creature, Diet};
+use crate::{
+    creature::Creature,
+    genome::{Gene, GeneKind, GENES},
+    lineage::Mutation,
+    stats::MutationStats,
+    Opts,
+};
+use clap::ArgEnum;
 use rand::Rng;
+use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MutationOperator {
+    /// Adds a random value between 0 and the scale to each gene
+    Uniform,
+    /// Adds a random value between -scale and scale to each gene
+    Symmetric,
+    /// Adds normally distributed noise with the configured sigma to each gene
+    Gaussian,
+    /// Nudges each gene like `Symmetric`, or sometimes resets it to a new random value
+    Creep,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MutationControl {
+    /// The probability and scale of the options are used throughout
+    Fixed,
+    /// Every creature carries its own probability and scale, which mutate before its genes do
+    /// and are inherited from the mean of its parents
+    SelfAdaptive,
+    /// The scale grows when more than a fifth of the mutated creatures end their generation among
+    /// the fittest fifth, and shrinks when less than a fifth do
+    OneFifth,
+    /// The probability and scale decrease linearly down to the floor
+    LinearDecay,
+    /// The probability and scale are multiplied by the decay every generation, down to the floor
+    ExponentialDecay,
+}
+
+/// Probability that a creature mutates and magnitude of the mutations of its continuous genes,
+/// which is the range of the offsets or their standard deviation depending on the operator
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MutationParams {
+    pub probability: f64,
+    pub scale: f64,
+}
+
+impl MutationParams {
+    /// Parameters given by the options
+    pub fn new(opts: &Opts) -> Self {
+        Self {
+            probability: opts.mutation_probability,
+            scale: match opts.mutation_operator {
+                MutationOperator::Gaussian => opts.mutation_sigma,
+                _ => opts.mutation_scale,
+            },
+        }
+    }
+
+    /// Parameters of a founder, which only carries its own in self-adaptive mode
+    pub fn carried(opts: &Opts) -> Option<Self> {
+        match opts.mutation_control {
+            MutationControl::SelfAdaptive => Some(Self::new(opts)),
+            _ => None,
+        }
+    }
+
+    /// Parameters of a child, which are the mean of those of its parents in self-adaptive mode
+    pub fn inherited(parent_1: Option<Self>, parent_2: Option<Self>, opts: &Opts) -> Option<Self> {
+        let initial = Self::carried(opts)?;
+        let (p1, p2) = (parent_1.unwrap_or(initial), parent_2.unwrap_or(initial));
+        Some(Self {
+            probability: (p1.probability + p2.probability) / 2.0,
+            scale: (p1.scale + p2.scale) / 2.0,
+        })
+    }
+
+    /// Updates the global parameters at the end of a generation with the creatures that lived
+    /// during it, dead or alive, for the schedules that apply to every creature
+    pub fn schedule<'a>(
+        &mut self,
+        creatures: impl Iterator<Item = &'a Creature> + Clone,
+        generation: usize,
+        opts: &Opts,
+    ) {
+        let initial = Self::new(opts);
+        let floor = opts.mutation_floor;
+        let factor = match opts.mutation_control {
+            MutationControl::Fixed | MutationControl::SelfAdaptive => return,
+
+            MutationControl::OneFifth => {
+                // Mutations of this generation succeed if the creature ended up among the fittest
+                // fifth, which a fifth of them do when they are neither good nor bad
+                let mut energies: Vec<f64> = creatures.clone().map(|c| c.energy).collect();
+                energies.sort_by(|a, b| b.partial_cmp(a).unwrap());
+                let threshold = energies.get(energies.len() / 5).copied().unwrap_or(0.0);
+
+                let (mut mutated, mut successes) = (0, 0);
+                for c in creatures {
+                    if matches!(c.ancestry.mutations.last(), Some(m) if m.generation == generation)
+                    {
+                        mutated += 1;
+                        if c.energy > threshold {
+                            successes += 1;
+                        }
+                    }
+                }
+                let rate = successes as f64 / mutated.max(1) as f64;
+                if mutated > 0 && rate > 0.2 {
+                    self.scale /= opts.one_fifth_factor;
+                } else if mutated > 0 && rate < 0.2 {
+                    self.scale *= opts.one_fifth_factor;
+                }
+                return;
+            }
+
+            MutationControl::LinearDecay => {
+                let progress = generation as f64 / opts.mutation_decay_generations.max(1) as f64;
+                1.0 - (1.0 - floor) * progress.min(1.0)
+            }
+
+            MutationControl::ExponentialDecay => {
+                opts.mutation_decay.powi(generation as i32).max(floor)
+            }
+        };
+        self.probability = (initial.probability * factor).clamp(0.0, 1.0);
+        self.scale = initial.scale * factor;
+    }
+
+    /// Mutates the parameters of a creature in self-adaptive mode, the scale with a log-normal
+    /// factor and the probability with a logit-normal one so that it stays within 0 and 1
+    pub fn adapt(&mut self, opts: &Opts, rng: &mut impl Rng) {
+        let rate = opts.mutation_learning_rate;
+        self.scale *= (rate * rng.sample::<f64, _>(StandardNormal)).exp();
+
+        let odds = self.probability / (1.0 - self.probability);
+        let odds = odds * (rate * rng.sample::<f64, _>(StandardNormal)).exp();
+        self.probability = odds / (1.0 + odds);
+        if !self.probability.is_finite() {
+            self.probability = 1.0;
+        }
+    }
+}
 
 impl Creature {
-    pub fn mutate(&mut self, scale: f64, rng: &mut impl Rng) {
-        let speed = self.speed + rng.gen_range(0.0, scale);
-        let stamina = self.stamina + rng.gen_range(0.0, scale);
-        let fov = self.fov + rng.gen_range(0.0, scale);
-        let size = self.size + rng.gen_range(0.0, scale);
+    /// Mutates the creature according to the configured operator and returns the indices of
+    /// the genes that were mutated, each allele mutating on its own in diploid mode
+    pub fn mutate(&mut self, scale: f64, opts: &Opts, rng: &mut impl Rng) -> Vec<usize> {
+        // Categorical genes flip with half the scale of the options, following the schedule or
+        // the parameters of the creature like the continuous genes do
+        let initial = MutationParams::new(opts).scale;
+        let flip = if initial > 0.0 {
+            (opts.mutation_scale / 2.0 * scale / initial).clamp(0.0, 0.5)
+        } else {
+            0.0
+        };
+
+        let mut mutated = Vec::new();
+        for (i, def) in GENES.iter().enumerate() {
+            if !def.is_enabled(opts) {
+                continue;
+            }
+            let mut changed = false;
+            for allele in self.genome.alleles_mut(i) {
+                match (&def.kind, allele) {
+                    (GeneKind::Continuous { bounds, .. }, Gene::Continuous(value)) => {
+                        if selected(opts, rng) {
+                            *value += mutation(*value, *bounds, scale, opts, rng);
+                            changed = true;
+                        }
+                    }
+
+                    (GeneKind::Categorical { .. }, Gene::Categorical(category)) => {
+                        if selected(opts, rng) && rng.gen_bool(flip) {
+                            let others: Vec<usize> = def
+                                .kind
+                                .enabled(opts)
+                                .into_iter()
+                                .filter(|&c| c != *category)
+                                .collect();
+                            if !others.is_empty() {
+                                *category = others[rng.gen_range(0, others.len())];
+                                changed = true;
+                            }
+                        }
+                    }
+
+                    _ => unreachable!(),
+                }
+            }
+            if changed {
+                mutated.push(i);
+            }
+        }
 
-        let capped_factor = 2.0 / (speed + stamina + fov + size);
+        self.genome.normalise(opts);
+        mutated
+    }
 
-        self.speed = speed * capped_factor;
-        self.stamina = stamina * capped_factor;
-        self.fov = fov * capped_factor;
-        self.size = size * capped_factor;
+    /// Mutates a newborn with its own parameters in self-adaptive mode or the global ones
+    /// otherwise, records the mutation in its ancestry and in the statistics, and returns the
+    /// parameters that were used
+    pub fn mutate_newborn(
+        &mut self,
+        global: MutationParams,
+        generation: usize,
+        stats: &mut MutationStats,
+        opts: &Opts,
+        rng: &mut impl Rng,
+    ) -> MutationParams {
+        let params = match (opts.mutation_control, &mut self.strategy) {
+            (MutationControl::SelfAdaptive, Some(strategy)) => {
+                strategy.adapt(opts, rng);
+                *strategy
+            }
+            _ => global,
+        };
 
-        if rng.gen_bool((scale / 2.0).min(0.5)) {
-            self.diet = match self.diet {
-                Diet::Herbivore => Diet::Carnivore,
-                Diet::Carnivore => Diet::Herbivore,
+        if rng.gen_bool(params.probability) {
+            let before = self.clone();
+            let genes = self.mutate(params.scale, opts, rng);
+            stats.record(&before, self, genes.len(), opts);
+            if !genes.is_empty() {
+                self.ancestry.mutations.push(Mutation { generation, genes });
             }
         }
+        params
+    }
+}
 
-        self.colour = Self::colour(self.speed, self.stamina, self.fov);
+/// Whether a single gene of a mutating creature mutates, which is always the case by default
+fn selected(opts: &Opts, rng: &mut impl Rng) -> bool {
+    opts.gene_mutation_probability >= 1.0 || rng.gen_bool(opts.gene_mutation_probability)
+}
+
+/// Offset to add to a single gene
+fn mutation(value: f64, bounds: (f64, f64), scale: f64, opts: &Opts, rng: &mut impl Rng) -> f64 {
+    if scale <= 0.0 {
+        return 0.0;
+    }
+
+    match opts.mutation_operator {
+        MutationOperator::Uniform => rng.gen_range(0.0, scale),
+        MutationOperator::Symmetric => rng.gen_range(-scale, scale),
+        MutationOperator::Gaussian => rng.sample::<f64, _>(StandardNormal) * scale,
+        MutationOperator::Creep => {
+            if rng.gen_bool(opts.mutation_reset_probability) {
+                bounds.0 + rng.gen::<f64>() * (bounds.1 - bounds.0) - value
+            } else {
+                rng.gen_range(-scale, scale)
+            }
+        }
     }
 }
@@ -0,0 +1,71 @@
+use crate::{creature::LivingCreature, world::World, Opts};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Evolution {
+    /// Generations end when few enough creatures are left, which are then replaced by the
+    /// children of the survivors
+    Generational,
+    /// Creatures reproduce during the round as soon as they have enough energy, so that births
+    /// and deaths never stop and the population is only limited by the food
+    SteadyState,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mating {
+    /// The child is a copy of its only parent
+    Asexual,
+    /// The child is bred with the closest creature in the field of view that can also reproduce
+    Sexual,
+}
+
+impl LivingCreature {
+    /// Whether the creature has enough energy to reproduce in steady-state mode
+    pub fn can_reproduce(&self, opts: &Opts) -> bool {
+        !self.is_dead() && self.creature.energy >= opts.reproduction_threshold
+    }
+}
+
+/// Indices of the parents of every child born during the current tick, both being the same
+/// creature in asexual mode
+pub fn parents(creatures: &[LivingCreature], world: &World, opts: &Opts) -> Vec<(usize, usize)> {
+    let ready: Vec<usize> = (0..creatures.len())
+        .filter(|&i| creatures[i].can_reproduce(opts))
+        .collect();
+
+    match opts.mating {
+        Mating::Asexual => ready.into_iter().map(|i| (i, i)).collect(),
+        Mating::Sexual => {
+            let mut paired = vec![false; ready.len()];
+            let mut parents = Vec::new();
+            for a in 0..ready.len() {
+                if paired[a] {
+                    continue;
+                }
+
+                let current = &creatures[ready[a]];
+                let fov = current.creature.fov(opts);
+                let mate = (a + 1..ready.len())
+                    .filter(|&b| !paired[b])
+                    .map(|b| {
+                        (
+                            b,
+                            world.distance(current.position, creatures[ready[b]].position),
+                        )
+                    })
+                    .filter(|&(_, distance)| distance < fov)
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+                if let Some((b, _)) = mate {
+                    paired[a] = true;
+                    paired[b] = true;
+                    parents.push((ready[a], ready[b]));
+                }
+            }
+            parents
+        }
+    }
+}
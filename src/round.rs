@@ -1,133 +1,217 @@
 use crate::{
-    creature::{Creature, Diet, LivingCreature},
-    food::Food,
+    creature::{Creature, Death, DeathCause, LivingCreature},
+    food::{Food, CARCASS, FOOD_KINDS},
+    grid::Grid,
+    world::World,
     Opts,
 };
+use clap::ArgEnum;
 use nalgebra::{Point2, Vector2};
 use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+const GRID_CELL_SIZE: f64 = 50.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TickMode {
+    /// Creatures sense and act one after the other, seeing the creatures that already moved
+    Sequential,
+    /// Every creature senses the same frozen state, then all of them act
+    TwoPhase,
+    /// Same as two-phase, with the sensing spread across threads
+    Parallel,
+}
+
+/// Everything a creature can sense during a tick
+struct Surroundings<'a> {
+    food: &'a [Food],
+    food_grid: &'a Grid,
+    creatures_grid: &'a Grid,
+    max_size: f64,
+    max_food_size: f64,
+    world: &'a World,
+    opts: &'a Opts,
+}
+
+/// Buffers reused between calls to `sense`
+#[derive(Default)]
+struct Scratch {
+    nearby: Vec<usize>,
+    predators: Vec<(Vector2<f64>, f64)>,
+}
+
+/// What a creature decided to do after sensing its surroundings
+struct Decision {
+    direction: Vector2<f64>,
+    hurry: bool,
+    food: Option<usize>,
+    prey: Option<usize>,
+}
 
 pub fn run(
-    creatures: &mut Vec<LivingCreature>,
+    creatures: &mut [LivingCreature],
     food: &mut Vec<Food>,
     rounds: usize,
     threshold: usize,
-    x: (f64, f64),
-    y: (f64, f64),
+    tick: &mut usize,
+    world: &World,
     opts: &Opts,
 ) -> bool {
     let mut ended = false;
 
-    let mut predators = Vec::with_capacity(creatures.len());
-    let zero_vector = Point2::new(0.0, 0.0) - Point2::new(0.0, 0.0);
+    let mut food_grid =
+        Grid::from_positions(food.iter().map(|f| f.position), world, GRID_CELL_SIZE);
+    let mut creatures_grid =
+        Grid::from_positions(creatures.iter().map(|c| c.position), world, GRID_CELL_SIZE);
+    let max_size = creatures
+        .iter()
+        .map(|c| c.creature.size(opts))
+        .fold(0.0, f64::max);
+    // Carcasses are as large as the creatures that left them
+    let max_food_size = FOOD_KINDS
+        .iter()
+        .map(|def| def.size)
+        .fold(max_size, f64::max);
+
+    let mut scratch = Scratch::default();
+    let mut decisions = Vec::with_capacity(creatures.len());
 
     for _ in 0..rounds {
-        for i in 0..creatures.len() {
-            let (first_half, second_half) = creatures.split_at_mut(i);
-            let (current, second_half) = second_half.split_first_mut().unwrap();
+        match opts.tick_mode {
+            TickMode::Sequential => {
+                for i in 0..creatures.len() {
+                    let (first_half, second_half) = creatures.split_at_mut(i);
+                    let (current, second_half) = second_half.split_first_mut().unwrap();
 
-            if current.is_dead() {
-                continue;
-            }
+                    if current.is_dead() {
+                        continue;
+                    }
 
-            let mut hurry = false;
+                    let surroundings = Surroundings {
+                        food,
+                        food_grid: &food_grid,
+                        creatures_grid: &creatures_grid,
+                        max_size,
+                        max_food_size,
+                        world,
+                        opts,
+                    };
+                    let decision = sense(
+                        &surroundings,
+                        &mut scratch,
+                        first_half,
+                        current,
+                        second_half,
+                    );
 
-            let (food_idx, prey_idx) = match current.creature.diet {
-                Diet::Herbivore => match find_food(&food, current, opts) {
-                    Some((mut v, _, i)) => {
-                        v.set_magnitude(0.125);
-                        current.direction += v;
-                        hurry = true;
+                    act(current, &decision, &mut creatures_grid, i, world, opts);
 
-                        (Some(i), None)
+                    if let Some(idx) = decision.food {
+                        eat_food(current, &mut food[idx], *tick, world, opts);
                     }
-                    None => (None, None),
-                },
-
-                Diet::Carnivore => match find_prey(first_half, second_half, current, opts) {
-                    Some((mut v, _, i)) => {
-                        v.set_magnitude(0.125);
-                        current.direction += v;
-                        hurry = true;
-
-                        (None, Some(i))
+                    if let Some(idx) = decision.prey {
+                        let prey = if idx < i {
+                            &mut first_half[idx]
+                        } else {
+                            &mut second_half[idx - i - 1]
+                        };
+                        eat_prey(current, prey, *tick, world, opts);
                     }
-                    None => (None, None),
-                },
-            };
-
-            predators.clear();
-            let mut min_distance = f64::MAX;
-            let mut max_distance = f64::MIN;
-            for (v, m) in find_predators(first_half, second_half, current, opts) {
-                predators.push((v, m));
-                min_distance = min_distance.min(m);
-                max_distance = max_distance.max(m);
+                }
             }
 
-            if predators.len() == 1 {
-                let (v, _) = &mut predators[0];
-                v.set_magnitude(0.15);
-                current.direction += *v;
-            } else if !predators.is_empty() {
-                let gap_factor = 1.0 / (max_distance - min_distance);
-                let mut predators_vector = zero_vector;
-
-                for (v, m) in &mut predators {
-                    let gap = *m - min_distance;
-                    v.set_magnitude(1.25 - gap * gap_factor);
-                    predators_vector += *v;
+            TickMode::TwoPhase | TickMode::Parallel => {
+                let surroundings = Surroundings {
+                    food,
+                    food_grid: &food_grid,
+                    creatures_grid: &creatures_grid,
+                    max_size,
+                    max_food_size,
+                    world,
+                    opts,
+                };
+                let snapshot = &creatures[..];
+                let sense_at = |scratch: &mut Scratch, i: usize| {
+                    let (first_half, second_half) = snapshot.split_at(i);
+                    let (current, second_half) = second_half.split_first().unwrap();
+                    if current.is_dead() {
+                        None
+                    } else {
+                        Some(sense(
+                            &surroundings,
+                            scratch,
+                            first_half,
+                            current,
+                            second_half,
+                        ))
+                    }
+                };
+
+                if opts.tick_mode == TickMode::Parallel {
+                    (0..snapshot.len())
+                        .into_par_iter()
+                        .map_init(Scratch::default, sense_at)
+                        .collect_into_vec(&mut decisions);
+                } else {
+                    decisions.clear();
+                    decisions.extend((0..snapshot.len()).map(|i| sense_at(&mut scratch, i)));
                 }
 
-                predators_vector.set_magnitude(predators_vector.magnitude().min(0.25));
-                current.direction += predators_vector;
-                hurry = true;
-            }
+                for (i, decision) in decisions.iter().enumerate() {
+                    if let Some(decision) = decision {
+                        act(
+                            &mut creatures[i],
+                            decision,
+                            &mut creatures_grid,
+                            i,
+                            world,
+                            opts,
+                        );
+                    }
+                }
 
-            let magnitude = current
-                .direction
-                .magnitude()
-                .min(current.creature.speed(hurry, opts));
-            current.direction.set_magnitude(magnitude);
-            current.creature.energy -=
-                2.0 / current.creature.speed(true, opts) * magnitude - current.creature.stamina;
-            current.position += current.direction;
-
-            if current.position.x < x.0 {
-                current.position.x = x.1 - (x.0 - current.position.x);
-            } else if current.position.x > x.1 {
-                current.position.x = x.0 + (current.position.x - x.1);
-            }
-            if current.position.y < y.0 {
-                current.position.y = y.1 - (y.0 - current.position.y);
-            } else if current.position.y > y.1 {
-                current.position.y = y.0 + (current.position.y - y.1);
-            }
+                // Conflicts over the same food or prey go to the creature with the lowest index
+                for (i, decision) in decisions.iter().enumerate() {
+                    let decision = match decision {
+                        Some(decision) if !creatures[i].eaten => decision,
+                        _ => continue,
+                    };
 
-            if let Some(idx) = food_idx {
-                let food = &mut food[idx];
-                let distance = (current.position - food.position).magnitude().abs();
-                if distance < current.creature.size(opts) {
-                    food.eaten = true;
-                    current.creature.energy = opts.max_energy.min(current.creature.energy + 500.0);
+                    if let Some(idx) = decision.food {
+                        if !food[idx].eaten {
+                            eat_food(&mut creatures[i], &mut food[idx], *tick, world, opts);
+                        }
+                    }
+                    if let Some(idx) = decision.prey {
+                        if !creatures[idx].eaten {
+                            let (current, prey) = pair_mut(creatures, i, idx);
+                            eat_prey(current, prey, *tick, world, opts);
+                        }
+                    }
                 }
             }
+        }
 
-            if let Some(idx) = prey_idx {
-                let prey = if idx < first_half.len() {
-                    &mut first_half[idx]
-                } else {
-                    &mut second_half[idx - first_half.len()]
-                };
-                let distance = (current.position - prey.position).magnitude().abs();
-                if distance < current.creature.size(opts) {
-                    prey.eaten = true;
-                    current.creature.energy = opts
-                        .max_energy
-                        .min(current.creature.energy + 500.0 + prey.creature.energy);
-                    prey.creature.energy = 0.0;
-                }
+        for f in food.iter_mut().filter(|f| !f.eaten) {
+            f.age();
+        }
+        for c in creatures.iter_mut() {
+            if c.death.is_none() && c.creature.energy <= 0.0 {
+                c.death = Some(Death {
+                    cause: DeathCause::Starved,
+                    killer: None,
+                    tick: *tick,
+                });
+                // The body is replaced by a carcass
+                c.eaten = true;
+                food_grid.insert(food.len(), c.position);
+                food.push(Food::carcass(&c.creature, c.position, opts));
             }
         }
+        *tick += 1;
 
         if creatures.iter().filter(|c| !c.is_dead()).count() <= threshold {
             ended = true;
@@ -135,26 +219,217 @@ pub fn run(
         }
     }
 
-    creatures.retain(|c| !c.eaten);
     food.retain(|f| !f.eaten);
     ended
 }
 
+/// Finds food, prey and predators around the current creature and steers accordingly
+fn sense(
+    surroundings: &Surroundings,
+    scratch: &mut Scratch,
+    first_half: &[LivingCreature],
+    current: &LivingCreature,
+    second_half: &[LivingCreature],
+) -> Decision {
+    let Surroundings {
+        food,
+        food_grid,
+        creatures_grid,
+        max_size,
+        max_food_size,
+        world,
+        opts,
+    } = *surroundings;
+    let Scratch { nearby, predators } = scratch;
+
+    let zero_vector = Point2::new(0.0, 0.0) - Point2::new(0.0, 0.0);
+    let mut direction = current.direction;
+    let mut hurry = false;
+
+    food_grid.query(
+        current.position,
+        current.creature.fov(opts) + max_food_size,
+        nearby,
+    );
+    let food_target = find_food(food, nearby, current, world, opts);
+    let prey_target = if current.creature.hunts() {
+        creatures_grid.query(
+            current.position,
+            current.creature.fov(opts) + max_size,
+            nearby,
+        );
+        find_prey(first_half, second_half, nearby, current, world, opts)
+    } else {
+        None
+    };
+
+    // The closest of the food and the prey is pursued
+    let (target, food_idx, prey_idx) = match (food_target, prey_target) {
+        (Some((v, m, i)), Some((_, prey_m, _))) if m <= prey_m => (Some(v), Some(i), None),
+        (_, Some((v, _, i))) => (Some(v), None, Some(i)),
+        (Some((v, _, i)), None) => (Some(v), Some(i), None),
+        (None, None) => (None, None, None),
+    };
+    if let Some(mut v) = target {
+        v.set_magnitude(0.125);
+        direction += v;
+        hurry = true;
+    }
+
+    predators.clear();
+    let mut min_distance = f64::MAX;
+    let mut max_distance = f64::MIN;
+    creatures_grid.query(
+        current.position,
+        current.creature.fov(opts) + max_size,
+        nearby,
+    );
+    for (v, m) in find_predators(first_half, second_half, nearby, current, world, opts) {
+        predators.push((v, m));
+        min_distance = min_distance.min(m);
+        max_distance = max_distance.max(m);
+    }
+
+    if predators.len() == 1 {
+        let (v, _) = &mut predators[0];
+        v.set_magnitude(0.15);
+        direction += *v;
+    } else if !predators.is_empty() {
+        let gap_factor = 1.0 / (max_distance - min_distance);
+        let mut predators_vector = zero_vector;
+
+        for (v, m) in predators.iter_mut() {
+            let gap = *m - min_distance;
+            v.set_magnitude(1.25 - gap * gap_factor);
+            predators_vector += *v;
+        }
+
+        predators_vector.set_magnitude(predators_vector.magnitude().min(0.25));
+        direction += predators_vector;
+        hurry = true;
+    }
+
+    Decision {
+        direction,
+        hurry,
+        food: food_idx,
+        prey: prey_idx,
+    }
+}
+
+/// Moves the current creature according to its decision
+fn act(
+    current: &mut LivingCreature,
+    decision: &Decision,
+    creatures_grid: &mut Grid,
+    i: usize,
+    world: &World,
+    opts: &Opts,
+) {
+    current.direction = decision.direction;
+    let magnitude = current
+        .direction
+        .magnitude()
+        .min(current.creature.speed(decision.hurry, opts));
+    current.direction.set_magnitude(magnitude);
+    current.creature.energy -=
+        2.0 / current.creature.speed(true, opts) * magnitude - current.creature.stamina(opts);
+    current.creature.energy -= current.creature.genome.upkeep(opts);
+    let previous_position = current.position;
+    current.position += current.direction;
+
+    world.constrain(&mut current.position, &mut current.direction);
+    creatures_grid.relocate(i, previous_position, current.position);
+}
+
+fn eat_food(
+    current: &mut LivingCreature,
+    food: &mut Food,
+    tick: usize,
+    world: &World,
+    opts: &Opts,
+) {
+    let distance = world.distance(current.position, food.position);
+    if distance < current.creature.size(opts) {
+        let mut energy = food.bite((FOOD_KINDS[food.kind].portion)(opts));
+        // Harmful food is never digested any less
+        if energy > 0.0 {
+            energy *= current.creature.digestion(food.kind == CARCASS, opts);
+        }
+        // A carcass eaten over several bites only counts as a meal once it is finished
+        if food.eaten {
+            current.meals[food.kind] += 1;
+            if food.kind == CARCASS {
+                current.scavenged.push(Death {
+                    cause: DeathCause::Scavenged,
+                    killer: Some(current.creature.diet()),
+                    tick,
+                });
+            }
+        }
+        current.creature.energy = opts.max_energy.min(current.creature.energy + energy);
+    }
+}
+
+fn eat_prey(
+    current: &mut LivingCreature,
+    prey: &mut LivingCreature,
+    tick: usize,
+    world: &World,
+    opts: &Opts,
+) {
+    let distance = world.distance(current.position, prey.position);
+    if distance < current.creature.size(opts) {
+        prey.eaten = true;
+        prey.death = Some(Death {
+            cause: DeathCause::Eaten,
+            killer: Some(current.creature.diet()),
+            tick,
+        });
+        let energy = (500.0 + prey.creature.energy) * current.creature.digestion(true, opts);
+        current.creature.energy = opts.max_energy.min(current.creature.energy + energy);
+        prey.creature.energy = 0.0;
+    }
+}
+
+/// Mutably borrows two different creatures at once
+fn pair_mut(
+    creatures: &mut [LivingCreature],
+    i: usize,
+    j: usize,
+) -> (&mut LivingCreature, &mut LivingCreature) {
+    if i < j {
+        let (first_half, second_half) = creatures.split_at_mut(j);
+        (&mut first_half[i], &mut second_half[0])
+    } else {
+        let (first_half, second_half) = creatures.split_at_mut(i);
+        (&mut second_half[0], &mut first_half[j])
+    }
+}
+
 fn find_food(
     food: &[Food],
+    nearby: &[usize],
     current: &LivingCreature,
+    world: &World,
     opts: &Opts,
 ) -> Option<(Vector2<f64>, f64, usize)> {
-    food.iter()
-        .enumerate()
-        .filter_map(|(i, f)| {
-            if f.eaten {
+    nearby
+        .iter()
+        .filter_map(|&i| {
+            let f = &food[i];
+            if f.eaten || !current.creature.can_eat(f) {
                 return None;
             }
 
-            let vector = f.position - current.position;
+            let vector = world.vector(current.position, f.position);
             let magnitude_abs = vector.magnitude().abs();
-            if magnitude_abs < current.creature.fov(opts) + 5.0 {
+            let fov = current.creature.fov(opts);
+            // Harmful food is only recognised up close, depending on discernment
+            if f.value() < 0.0 && magnitude_abs < fov * current.creature.discernment(opts) {
+                return None;
+            }
+            if magnitude_abs < fov + f.size {
                 Some((vector, magnitude_abs, i))
             } else {
                 None
@@ -163,22 +438,36 @@ fn find_food(
         .fold(None, food_prey_folder)
 }
 
+/// Looks up the creature at index `i` of the full list, given the halves around the current one
+fn nearby_creature<'a>(
+    first_half: &'a [LivingCreature],
+    second_half: &'a [LivingCreature],
+    i: usize,
+) -> Option<&'a LivingCreature> {
+    match i.cmp(&first_half.len()) {
+        Ordering::Less => Some(&first_half[i]),
+        Ordering::Equal => None,
+        Ordering::Greater => Some(&second_half[i - first_half.len() - 1]),
+    }
+}
+
 fn find_prey(
     first_half: &[LivingCreature],
     second_half: &[LivingCreature],
+    nearby: &[usize],
     current: &LivingCreature,
+    world: &World,
     opts: &Opts,
 ) -> Option<(Vector2<f64>, f64, usize)> {
-    first_half
+    nearby
         .iter()
-        .chain(second_half.iter())
-        .enumerate()
-        .filter_map(|(i, c)| {
-            if c.eaten || !current.creature.can_prey_on(c.creature) {
+        .filter_map(|&i| {
+            let c = nearby_creature(first_half, second_half, i)?;
+            if c.eaten || !current.creature.can_prey_on(&c.creature) {
                 return None;
             }
 
-            let vector = c.position - current.position;
+            let vector = world.vector(current.position, c.position);
             let magnitude_abs = vector.magnitude().abs();
             if magnitude_abs < current.creature.fov(opts) + c.creature.size(opts) {
                 Some((vector, magnitude_abs, i))
@@ -190,27 +479,27 @@ fn find_prey(
 }
 
 fn find_predators<'a>(
-    first_half: &'a [LivingCreature<'a>],
-    second_half: &'a [LivingCreature<'a>],
-    current: &'a LivingCreature<'a>,
+    first_half: &'a [LivingCreature],
+    second_half: &'a [LivingCreature],
+    nearby: &'a [usize],
+    current: &'a LivingCreature,
+    world: &'a World,
     opts: &'a Opts,
 ) -> impl Iterator<Item = (Vector2<f64>, f64)> + 'a {
-    first_half
-        .iter()
-        .chain(second_half.iter())
-        .filter_map(move |p| {
-            if p.is_dead() || !p.creature.can_prey_on(current.creature) {
-                return None;
-            }
+    nearby.iter().filter_map(move |&i| {
+        let p = nearby_creature(first_half, second_half, i)?;
+        if p.is_dead() || !p.creature.can_prey_on(&current.creature) {
+            return None;
+        }
 
-            let vector = current.position - p.position;
-            let magnitude_abs = vector.magnitude().abs();
-            if magnitude_abs < current.creature.fov(opts) + p.creature.size(opts) {
-                Some((vector, magnitude_abs))
-            } else {
-                None
-            }
-        })
+        let vector = world.vector(p.position, current.position);
+        let magnitude_abs = vector.magnitude().abs();
+        if magnitude_abs < current.creature.fov(opts) + p.creature.size(opts) {
+            Some((vector, magnitude_abs))
+        } else {
+            None
+        }
+    })
 }
 
 fn food_prey_folder(
@@ -230,31 +519,53 @@ fn food_prey_folder(
     }
 }
 
-pub fn generate_food(
-    food_buf: &mut Vec<Food>,
-    qty: usize,
-    x: (f64, f64),
-    y: (f64, f64),
-    rng: &mut impl Rng,
-) {
-    food_buf.clear();
-    for _ in 0..qty {
-        food_buf.push(Food::random(x, y, rng));
-    }
-}
-
-pub fn position_creatures<'a>(
-    creatures: &'a mut [Creature],
-    creatures_buf: &mut Vec<LivingCreature<'a>>,
-    x: (f64, f64),
-    y: (f64, f64),
+pub fn position_creatures(
+    creatures: impl IntoIterator<Item = Creature>,
+    creatures_buf: &mut Vec<LivingCreature>,
+    world: &World,
     rng: &mut impl Rng,
 ) {
     creatures_buf.clear();
     for c in creatures
-        .iter_mut()
-        .map(|c| LivingCreature::random(c, x, y, rng))
+        .into_iter()
+        .map(|c| LivingCreature::random(c, world.x, world.y, rng))
     {
         creatures_buf.push(c);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{Simulation, Step};
+    use clap::Clap;
+
+    /// Seeded simulation advanced by two generations and part of a third one
+    fn simulate(tick_mode: &str) -> Simulation {
+        let opts = Opts::parse_from(&[
+            "session-0sw",
+            "--headless",
+            "--seed",
+            "7",
+            "--tick-mode",
+            tick_mode,
+        ]);
+        let mut simulation = Simulation::new(opts).unwrap();
+        for _ in 0..2 {
+            assert_eq!(simulation.step_generation(), Step::Generation);
+        }
+        simulation.step_ticks(100);
+        simulation
+    }
+
+    #[test]
+    fn parallel_matches_two_phase() {
+        let (two_phase, parallel) = (simulate("two-phase"), simulate("parallel"));
+        assert_eq!(two_phase.generation(), parallel.generation());
+        assert_eq!(two_phase.tick(), parallel.tick());
+        for (a, b) in two_phase.islands().iter().zip(parallel.islands()) {
+            assert_eq!(a.creatures(), b.creatures());
+            assert_eq!(a.food(), b.food());
+        }
+    }
+}
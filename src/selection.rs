@@ -1,42 +1,174 @@
-use rand::Rng;
+use clap::ArgEnum;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 
-use crate::{creature::Creature, Opts};
+use crate::{
+    creature::Creature,
+    lineage::Genealogy,
+    speciation::{self, Niching},
+    Opts,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Selection {
+    /// Parents are picked with a probability proportional to their energy
+    Roulette,
+    /// The fittest of a few random creatures is picked
+    Tournament,
+    /// Parents are picked with a probability proportional to their rank
+    Rank,
+    /// Parents are picked uniformly among the fittest creatures
+    Truncation,
+    /// Like the roulette, but every parent is drawn at once with evenly spaced pointers
+    StochasticUniversal,
+    /// Only the fittest creatures survive, and parents are picked among them like the roulette
+    Elitist,
+}
+
+/// Parents available to breed the next generation, ready to be picked according to the strategy
+enum Pool {
+    /// Cumulative weight of every creature
+    Weighted(Vec<f64>),
+    Tournament {
+        fitness: Vec<f64>,
+        size: usize,
+    },
+    Uniform(Vec<usize>),
+    /// Parents drawn in advance, picked from the end
+    Drawn(Vec<usize>),
+}
 
 impl Creature {
-    pub fn select(creatures: &mut Vec<Self>, qty: usize, opts: &Opts, rng: &mut impl Rng) {
-        let mut total_energy = 0.0;
-        for c in creatures.iter() {
-            total_energy += c.energy;
+    pub fn select(
+        creatures: &mut Vec<Self>,
+        qty: usize,
+        genealogy: &mut Genealogy,
+        opts: &Opts,
+        rng: &mut impl Rng,
+    ) {
+        let mut fitness = speciation::fitness(creatures, opts);
+        if opts.selection == Selection::Elitist {
+            let mut fittest = ranked(&fitness);
+            fittest.truncate(opts.elite_count.max(1));
+            *creatures = fittest.iter().map(|&i| creatures[i].clone()).collect();
+            fitness = fittest.iter().map(|&i| fitness[i]).collect();
         }
 
-        while creatures.len() < qty {
-            let idx1 = rng.gen_range(0.0, total_energy);
-            let idx2 = rng.gen_range(0.0, total_energy);
-
-            let mut c1 = None;
-            let mut i1 = 0.0;
-            for c in creatures.iter() {
-                i1 += c.energy;
-                if i1 >= idx1 {
-                    c1 = Some(c);
-                    break;
+        let children = qty.saturating_sub(creatures.len());
+        if opts.niching == Niching::Quotas {
+            for (members, quota) in speciation::quotas(creatures, &fitness, children) {
+                let member_fitness: Vec<f64> = members.iter().map(|&i| fitness[i]).collect();
+                let mut pool = Pool::new(&member_fitness, quota * 2, opts, rng);
+
+                for _ in 0..quota {
+                    let c1 = &creatures[members[pool.pick(rng)]];
+                    let c2 = &creatures[members[pool.pick(rng)]];
+
+                    let c = Creature::breed(c1, c2, genealogy, opts, rng);
+                    creatures.push(c);
                 }
             }
-            let c1 = c1.unwrap();
-
-            let mut c2 = None;
-            let mut i2 = 0.0;
-            for c in creatures.iter() {
-                i2 += c.energy;
-                if i2 >= idx2 {
-                    c2 = Some(c);
-                    break;
+            return;
+        }
+
+        let mut pool = Pool::new(&fitness, children * 2, opts, rng);
+        while creatures.len() < qty {
+            let c1 = &creatures[pool.pick(rng)];
+            let c2 = &creatures[pool.pick(rng)];
+
+            let c = Creature::breed(c1, c2, genealogy, opts, rng);
+            creatures.push(c);
+        }
+    }
+}
+
+impl Pool {
+    fn new(fitness: &[f64], picks: usize, opts: &Opts, rng: &mut impl Rng) -> Self {
+        match opts.selection {
+            Selection::Roulette | Selection::Elitist => {
+                Self::Weighted(cumulative(fitness.iter().copied()))
+            }
+
+            Selection::Tournament => Self::Tournament {
+                fitness: fitness.to_vec(),
+                size: opts.tournament_size.max(1),
+            },
+
+            Selection::Rank => {
+                let mut ranks = vec![0.0; fitness.len()];
+                for (rank, i) in ranked(fitness).into_iter().rev().enumerate() {
+                    ranks[i] = (rank + 1) as f64;
                 }
+                Self::Weighted(cumulative(ranks))
             }
-            let c2 = c2.unwrap();
 
-            let c = Creature::breed(c1, c2, opts, rng);
-            creatures.push(c);
+            Selection::Truncation => {
+                let mut fittest = ranked(fitness);
+                let count = (fitness.len() as f64 * opts.truncation_ratio).ceil() as usize;
+                fittest.truncate(count.max(1));
+                Self::Uniform(fittest)
+            }
+
+            Selection::StochasticUniversal => {
+                let cumulative = cumulative(fitness.iter().copied());
+                let total = cumulative.last().copied().unwrap_or_default();
+                let step = total / picks.max(1) as f64;
+                let start = rng.gen_range(0.0, step);
+
+                let mut drawn: Vec<usize> = (0..picks)
+                    .map(|i| find(&cumulative, start + i as f64 * step))
+                    .collect();
+                // Neighbouring pointers mostly land on the same creature
+                drawn.shuffle(rng);
+                Self::Drawn(drawn)
+            }
+        }
+    }
+
+    fn pick(&mut self, rng: &mut impl Rng) -> usize {
+        match self {
+            Self::Weighted(cumulative) => {
+                let total = cumulative[cumulative.len() - 1];
+                find(cumulative, rng.gen_range(0.0, total))
+            }
+
+            Self::Tournament { fitness, size } => (0..*size)
+                .map(|_| rng.gen_range(0, fitness.len()))
+                .fold(None, |best: Option<usize>, i| match best {
+                    Some(b) if fitness[b] >= fitness[i] => Some(b),
+                    _ => Some(i),
+                })
+                .unwrap(),
+
+            Self::Uniform(indices) => *indices.choose(rng).unwrap(),
+
+            Self::Drawn(drawn) => drawn.pop().unwrap(),
         }
     }
 }
+
+fn cumulative(weights: impl IntoIterator<Item = f64>) -> Vec<f64> {
+    let mut total = 0.0;
+    weights
+        .into_iter()
+        .map(|w| {
+            total += w;
+            total
+        })
+        .collect()
+}
+
+/// Index of the first creature whose cumulative weight reaches `value`
+fn find(cumulative: &[f64], value: f64) -> usize {
+    cumulative
+        .partition_point(|&w| w < value)
+        .min(cumulative.len() - 1)
+}
+
+/// Indices of the creatures from the fittest to the least fit
+fn ranked(fitness: &[f64]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..fitness.len()).collect();
+    indices.sort_by(|&a, &b| fitness[b].partial_cmp(&fitness[a]).unwrap());
+    indices
+}
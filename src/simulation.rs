@@ -0,0 +1,401 @@
+use crate::{
+    creature::{Creature, Death, LivingCreature},
+    island::{self, Island},
+    lineage::Genealogy,
+    reproduction::Evolution,
+    speciation::Speciation,
+    stats::{Meals, MutationStats, Stats},
+    Opts,
+};
+use anyhow::Error;
+use chrono::Local;
+use rand::prelude::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// Outcome of advancing a simulation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// The current generation is still going
+    Running,
+    /// The current generation ended and the next one was spawned
+    Generation,
+    /// The statistics were sampled in steady-state mode
+    Sample,
+    /// Every creature of every island died and the simulation can't go on
+    Extinct,
+}
+
+/// Complete state of a simulation, advanced tick by tick or generation by generation, a
+/// generation being the time between two samples in steady-state mode
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Simulation {
+    opts: Opts,
+    seed: u64,
+    rng: Pcg64Mcg,
+
+    islands: Vec<Island>,
+
+    generation: usize,
+    /// Ticks elapsed since the start of the simulation in steady-state mode, which keeps counting
+    /// while some islands are extinct
+    tick: usize,
+    /// Statistics of every island put together
+    stats: Vec<Stats>,
+    genealogy: Genealogy,
+    speciation: Speciation,
+}
+
+impl Simulation {
+    /// Creates a simulation with a random first generation on every island, seeded with
+    /// `opts.seed` if present
+    pub fn new(mut opts: Opts) -> Result<Self, Error> {
+        let seed = opts.seed.unwrap_or_else(rand::random);
+        opts.seed = Some(seed);
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
+
+        let mut islands = (0..opts.islands.max(1))
+            .map(|i| {
+                Ok(Island::new(
+                    opts.island(i)?,
+                    seed.wrapping_add(i as u64 + 1),
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut genealogy = Genealogy::new();
+        let mut populations: Vec<Vec<Creature>> = islands
+            .iter()
+            .map(|island| {
+                let opts = island.opts();
+                (0..opts.creature_count)
+                    .map(|_| Creature::random(opts, &mut genealogy, &mut rng))
+                    .collect()
+            })
+            .collect();
+        let mut speciation = Speciation::default();
+        speciation.assign(populations.iter_mut().flatten(), &opts);
+
+        for (island, creatures) in islands.iter_mut().zip(populations) {
+            island.spawn(creatures, &mut rng);
+        }
+
+        Ok(Self {
+            seed,
+            rng,
+
+            islands,
+
+            generation: 1,
+            tick: 0,
+            stats: Vec::new(),
+            genealogy,
+            speciation,
+
+            opts,
+        })
+    }
+
+    /// Advances the simulation by a single tick
+    pub fn step_tick(&mut self) -> Step {
+        self.step_ticks(1)
+    }
+
+    /// Advances every island by up to `ticks` ticks, stopping early if the generation ends on
+    /// all of them or if the statistics are sampled
+    pub fn step_ticks(&mut self, ticks: usize) -> Step {
+        if self.opts.evolution == Evolution::SteadyState {
+            return self.step_steady_state(ticks);
+        }
+
+        let mut ended = true;
+        for island in self.islands.iter_mut() {
+            ended &= island.step_ticks(ticks);
+        }
+
+        if ended {
+            self.next_generation()
+        } else {
+            Step::Running
+        }
+    }
+
+    /// Advances the simulation until the current generation ends, or until the next sample in
+    /// steady-state mode
+    pub fn step_generation(&mut self) -> Step {
+        loop {
+            match self.step_ticks(usize::MAX) {
+                Step::Running => (),
+                step => return step,
+            }
+        }
+    }
+
+    /// Restores a simulation from a checkpoint written by `save` or `checkpoint`
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(bincode::deserialize_from(reader)?)
+    }
+
+    /// Writes the complete state of the simulation to a file
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(writer, self)?;
+        Ok(())
+    }
+
+    /// Saves the simulation in the `checkpoints` folder and returns the path of the file
+    pub fn checkpoint(&self) -> Result<PathBuf, Error> {
+        let title = Local::now().format("%Y-%m-%d-%H-%M-%S");
+
+        fs::create_dir_all("checkpoints")?;
+        let path = Path::new("checkpoints")
+            .join(format!("{}-{}-{}.bin", title, self.seed, self.generation));
+
+        self.save(&path)?;
+        Ok(path)
+    }
+
+    pub fn opts(&self) -> &Opts {
+        &self.opts
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn islands(&self) -> &[Island] {
+        &self.islands
+    }
+
+    /// Current generation, starting at 1
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Ticks elapsed since the start of the current generation, or since the start of the
+    /// simulation in steady-state mode
+    pub fn tick(&self) -> usize {
+        match self.opts.evolution {
+            Evolution::Generational => self.islands[0].tick(),
+            Evolution::SteadyState => self.tick,
+        }
+    }
+
+    /// Every creature that ever lived in the simulation
+    pub fn genealogy(&self) -> &Genealogy {
+        &self.genealogy
+    }
+
+    /// Statistics of every completed generation, or of every sample, all islands put together
+    pub fn stats(&self) -> &[Stats] {
+        &self.stats
+    }
+
+    /// Replaces dead creatures with new ones and applies mutations on every island, exchanges
+    /// migrants between them and spawns the next generation
+    fn next_generation(&mut self) -> Step {
+        let mut deaths: Vec<Vec<Death>> = Vec::with_capacity(self.islands.len());
+        let mut meals: Vec<Meals> = Vec::with_capacity(self.islands.len());
+        let mut populations: Vec<Vec<Creature>> = Vec::with_capacity(self.islands.len());
+        for island in self.islands.iter_mut() {
+            let creatures = island.drain(self.generation);
+            deaths.push(creatures.iter().flat_map(LivingCreature::deaths).collect());
+            meals.push(Meals::collect(&creatures));
+            populations.push(
+                creatures
+                    .into_iter()
+                    .map(|c| c.creature)
+                    .filter(|c| c.energy > 0.0)
+                    .collect(),
+            );
+        }
+        if populations.iter().all(Vec::is_empty) {
+            return Step::Extinct;
+        }
+
+        let rng = &mut self.rng;
+        let genealogy = &mut self.genealogy;
+        genealogy.generation = self.generation + 1;
+
+        let mut mutations = vec![MutationStats::default(); self.islands.len()];
+        for ((island, creatures), mutations) in self
+            .islands
+            .iter()
+            .zip(&mut populations)
+            .zip(&mut mutations)
+        {
+            // An island without survivors waits for migrants
+            if creatures.is_empty() {
+                continue;
+            }
+
+            let opts = island.opts();
+            Creature::select(creatures, opts.creature_count, genealogy, opts, rng);
+            let mut used = Vec::with_capacity(creatures.len());
+            for c in creatures.iter_mut() {
+                used.push(c.mutate_newborn(
+                    island.mutation(),
+                    genealogy.generation,
+                    mutations,
+                    opts,
+                    rng,
+                ));
+                c.energy = opts.start_energy;
+            }
+            mutations.params(&used);
+        }
+
+        if self.islands.len() > 1
+            && self
+                .generation
+                .is_multiple_of(self.opts.migration_interval.max(1))
+        {
+            island::migrate(&mut populations, &self.opts, rng);
+        }
+
+        for creatures in &populations {
+            genealogy.update(creatures);
+        }
+        self.speciation
+            .assign(populations.iter_mut().flatten(), &self.opts);
+
+        for ((((island, creatures), deaths), meals), mutations) in self
+            .islands
+            .iter_mut()
+            .zip(&populations)
+            .zip(&deaths)
+            .zip(&meals)
+            .zip(&mutations)
+        {
+            island.push_stats(Stats::collect(
+                creatures,
+                deaths,
+                meals.clone(),
+                *mutations,
+                island.opts(),
+            ));
+        }
+        let all: Vec<Creature> = populations.iter().flatten().cloned().collect();
+        let deaths: Vec<Death> = deaths.into_iter().flatten().collect();
+        self.stats.push(Stats::collect(
+            &all,
+            &deaths,
+            Meals::sum(&meals),
+            MutationStats::sum(&mutations),
+            &self.opts,
+        ));
+
+        self.generation += 1;
+        for (island, creatures) in self.islands.iter_mut().zip(populations) {
+            island.spawn(creatures, &mut self.rng);
+        }
+        Step::Generation
+    }
+
+    /// Advances every island tick by tick, breeding the creatures that have enough energy after
+    /// every tick, until the statistics are sampled
+    fn step_steady_state(&mut self, ticks: usize) -> Step {
+        for _ in 0..ticks {
+            let mut extinct = true;
+            for island in self.islands.iter_mut() {
+                extinct &= island.step_ticks(1);
+                island.reproduce(&mut self.genealogy);
+            }
+            if extinct {
+                return Step::Extinct;
+            }
+
+            self.tick += 1;
+            if self.tick.is_multiple_of(self.opts.sample_interval.max(1)) {
+                self.sample();
+                return Step::Sample;
+            }
+        }
+        Step::Running
+    }
+
+    /// Removes the dead creatures of every island and records their statistics, exchanging
+    /// migrants between the islands at the configured interval
+    fn sample(&mut self) {
+        let mut deaths: Vec<Vec<Death>> = Vec::with_capacity(self.islands.len());
+        let mut meals: Vec<Meals> = Vec::with_capacity(self.islands.len());
+        let mut mutations: Vec<MutationStats> = Vec::with_capacity(self.islands.len());
+        for island in self.islands.iter_mut() {
+            let (d, m, mu) = island.sample(self.generation);
+            deaths.push(d);
+            meals.push(m);
+            mutations.push(mu);
+        }
+
+        let count = self.islands.len();
+        if count > 1
+            && self
+                .generation
+                .is_multiple_of(self.opts.migration_interval.max(1))
+        {
+            let mut arrivals = vec![Vec::new(); count];
+            for (i, island) in self.islands.iter_mut().enumerate() {
+                for j in island::destinations(i, count, &self.opts) {
+                    arrivals[j].extend(island.emigrate(self.opts.migrants, &mut self.rng));
+                }
+            }
+            for (island, arrivals) in self.islands.iter_mut().zip(arrivals) {
+                island.immigrate(arrivals, &mut self.rng);
+            }
+        }
+
+        self.speciation.assign(
+            self.islands.iter_mut().flat_map(Island::creatures_mut),
+            &self.opts,
+        );
+        let populations: Vec<Vec<Creature>> = self
+            .islands
+            .iter()
+            .map(|island| {
+                island
+                    .creatures()
+                    .iter()
+                    .map(|c| c.creature.clone())
+                    .collect()
+            })
+            .collect();
+        for creatures in &populations {
+            self.genealogy.update(creatures);
+        }
+
+        for ((((island, creatures), deaths), meals), mutations) in self
+            .islands
+            .iter_mut()
+            .zip(&populations)
+            .zip(&deaths)
+            .zip(&meals)
+            .zip(&mutations)
+        {
+            island.push_stats(Stats::collect(
+                creatures,
+                deaths,
+                meals.clone(),
+                *mutations,
+                island.opts(),
+            ));
+        }
+        let all: Vec<Creature> = populations.into_iter().flatten().collect();
+        let deaths: Vec<Death> = deaths.into_iter().flatten().collect();
+        self.stats.push(Stats::collect(
+            &all,
+            &deaths,
+            Meals::sum(&meals),
+            MutationStats::sum(&mutations),
+            &self.opts,
+        ));
+
+        self.generation += 1;
+        self.genealogy.generation = self.generation;
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    creature::Creature,
+    genome::{Gene, GeneKind, Genome, GENES},
+    Opts,
+};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Niching {
+    /// Species are only reported
+    None,
+    /// The fitness of every creature is divided by the size of its species
+    Sharing,
+    /// Every species breeds a share of the children proportional to its mean fitness, with
+    /// parents picked within the species
+    Quotas,
+}
+
+/// Group of similar creatures, which persists across generations as long as it has members
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Species {
+    id: u64,
+    /// Genome that new members are compared to, taken from a member every generation
+    representative: Genome,
+    size: usize,
+}
+
+/// Every species currently alive in a simulation
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Speciation {
+    species: Vec<Species>,
+    next_id: u64,
+}
+
+impl Speciation {
+    /// Sorts the creatures into species, creating a new one for every creature too far from the
+    /// existing species and removing the species that have no members left
+    pub fn assign<'a>(
+        &mut self,
+        creatures: impl IntoIterator<Item = &'a mut Creature>,
+        opts: &Opts,
+    ) {
+        let existing = self.species.len();
+        let mut represented = vec![false; existing];
+        for s in self.species.iter_mut() {
+            s.size = 0;
+        }
+
+        for c in creatures {
+            let threshold = opts.species_threshold;
+            match self
+                .species
+                .iter()
+                .position(|s| distance(&s.representative, &c.genome, opts) < threshold)
+            {
+                Some(i) => {
+                    let species = &mut self.species[i];
+                    species.size += 1;
+                    if i < existing && !represented[i] {
+                        species.representative = c.genome.clone();
+                        represented[i] = true;
+                    }
+                    c.species = species.id;
+                }
+                None => {
+                    self.species.push(Species {
+                        id: self.next_id,
+                        representative: c.genome.clone(),
+                        size: 1,
+                    });
+                    c.species = self.next_id;
+                    self.next_id += 1;
+                }
+            }
+        }
+
+        self.species.retain(|s| s.size > 0);
+    }
+}
+
+/// Distance between two genomes, which is the sum of the differences of the continuous genes
+/// relative to their bounds, plus 1 for every categorical gene that differs, leaving out the genes
+/// that are disabled with the options
+pub fn distance(a: &Genome, b: &Genome, opts: &Opts) -> f64 {
+    GENES
+        .iter()
+        .zip(a.genes().iter().zip(b.genes()))
+        .filter(|(def, _)| def.is_enabled(opts))
+        .map(|(def, genes)| match (&def.kind, genes) {
+            (GeneKind::Continuous { bounds, .. }, (Gene::Continuous(a), Gene::Continuous(b))) => {
+                (a - b).abs() / (bounds.1 - bounds.0)
+            }
+            (GeneKind::Categorical { .. }, (Gene::Categorical(a), Gene::Categorical(b))) => {
+                if a == b {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            _ => unreachable!(),
+        })
+        .sum()
+}
+
+/// Fitness of every creature used to pick parents, shared within species if enabled
+pub fn fitness(creatures: &[Creature], opts: &Opts) -> Vec<f64> {
+    match opts.niching {
+        Niching::None | Niching::Quotas => creatures.iter().map(|c| c.energy).collect(),
+        Niching::Sharing => {
+            let sizes = sizes(creatures);
+            creatures
+                .iter()
+                .map(|c| c.energy / sizes[&c.species] as f64)
+                .collect()
+        }
+    }
+}
+
+/// Indices of the members of every species along with the number of children it breeds, which
+/// is proportional to its mean fitness and adds up to `children`
+pub fn quotas(
+    creatures: &[Creature],
+    fitness: &[f64],
+    children: usize,
+) -> Vec<(Vec<usize>, usize)> {
+    let mut members: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (i, c) in creatures.iter().enumerate() {
+        members.entry(c.species).or_default().push(i);
+    }
+
+    let means: Vec<f64> = members
+        .values()
+        .map(|m| m.iter().map(|&i| fitness[i]).sum::<f64>() / m.len() as f64)
+        .collect();
+    let total: f64 = means.iter().sum();
+
+    // Largest remainder method, falling back to equal shares when no species has any fitness
+    let shares: Vec<f64> = means
+        .iter()
+        .map(|&mean| {
+            if total > 0.0 {
+                mean / total * children as f64
+            } else {
+                children as f64 / means.len() as f64
+            }
+        })
+        .collect();
+    let mut quotas: Vec<usize> = shares.iter().map(|s| s.floor() as usize).collect();
+    let mut remainders: Vec<usize> = (0..shares.len()).collect();
+    remainders.sort_by(|&a, &b| {
+        let (a, b) = (shares[a] - shares[a].floor(), shares[b] - shares[b].floor());
+        b.partial_cmp(&a).unwrap()
+    });
+    let missing = children - quotas.iter().sum::<usize>();
+    for &i in remainders.iter().take(missing) {
+        quotas[i] += 1;
+    }
+
+    members
+        .into_iter()
+        .zip(quotas)
+        .map(|((_, m), quota)| (m, quota))
+        .collect()
+}
+
+/// Number of creatures of every species
+pub fn sizes(creatures: &[Creature]) -> BTreeMap<u64, usize> {
+    let mut sizes = BTreeMap::new();
+    for c in creatures {
+        *sizes.entry(c.species).or_insert(0) += 1;
+    }
+    sizes
+}
@@ -1,144 +1,235 @@
-use crate::creature::{Creature, Diet};
+use crate::{
+    creature::{Creature, Death, DeathCause, Diet, LivingCreature},
+    food::FOOD_KINDS,
+    genome::{self, Budget, Gene, GeneKind, DIET},
+    lineage::Genealogy,
+    mutation::MutationParams,
+    reproduction::Evolution,
+    speciation, Opts,
+};
 use anyhow::Error;
 use chrono::Local;
 use plotters::{
-    prelude::{ChartBuilder, IntoDrawingArea, LineSeries, PathElement, SVGBackend},
-    style::{Color, IntoFont, RGBColor, BLACK, WHITE},
+    prelude::{AreaSeries, ChartBuilder, IntoDrawingArea, LineSeries, PathElement, SVGBackend},
+    style::{Color, HSLColor, IntoFont, RGBColor, BLACK, WHITE},
+};
+use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
 };
-use std::{fs, path::Path};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Stats {
     all: StatsInner,
     count: usize,
+    /// Mean cost of the traits according to the budget model
+    cost: f64,
 
     herbivores: StatsInner,
     herbivores_count: usize,
 
     carnivores: StatsInner,
     carnivores_count: usize,
+
+    scavengers: StatsInner,
+    scavengers_count: usize,
+
+    omnivores: StatsInner,
+    omnivores_count: usize,
+
+    deaths: DeathStats,
+    meals: Meals,
+    mutations: MutationStats,
+    /// Founders that still have descendants in the population
+    founders: usize,
+    species_count: usize,
+    /// Number of creatures of every species, by id
+    species: BTreeMap<u64, usize>,
+    /// Only collected in diploid mode
+    heterozygosity: Option<Heterozygosity>,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Heterozygosity {
+    /// Share of the creatures whose diet alleles differ, unless the diet is continuous
+    diet: Option<f64>,
+    /// Share of the diet alleles that are carnivore, expressed or not, unless the diet is
+    /// continuous
+    carnivore_alleles: Option<f64>,
+    /// Mean difference between the alleles of the continuous genes, relative to their bounds
+    traits: f64,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
-pub struct StatsInner {
-    speed: f64,
-    stamina: f64,
-    fov: f64,
-    size: f64,
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeathStats {
+    starved: usize,
+    eaten: usize,
+    /// Starved creatures whose carcasses were finished, which are also counted as starved
+    scavenged: usize,
+    /// Creatures eaten alive or as a carcass, by diet of the creature that ate them
+    killers: BTreeMap<Diet, usize>,
 }
 
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+pub struct MutationStats {
+    /// Creatures that went through the mutation operator
+    creatures: usize,
+    /// Genes that were mutated, the diet included
+    genes: usize,
+    diet_flips: usize,
+    /// Mean absolute change of the capped traits per mutated creature, after normalisation
+    delta: f64,
+    /// Mean mutation parameters of every creature of the generation
+    probability: f64,
+    scale: f64,
+    /// Creatures whose parameters were averaged
+    #[serde(skip)]
+    population: usize,
+}
+
+/// Food eaten during the generation by type, a carcass counting once it is finished, serialised
+/// as a map from the name of the type
+#[derive(Debug, Clone, Default)]
+pub struct Meals(Vec<usize>);
+
+/// Mean of every continuous gene, or `None` for the genes that are disabled with the options,
+/// serialised as a map from the name of the gene
+#[derive(Debug, Clone, Default)]
+pub struct StatsInner(Vec<Option<f64>>);
+
 impl Stats {
-    pub fn collect(creatures: &[Creature]) -> Self {
-        let mut all = StatsInner::default();
-        let mut herbivores = StatsInner::default();
-        let mut carnivores = StatsInner::default();
+    pub fn collect(
+        creatures: &[Creature],
+        deaths: &[Death],
+        meals: Meals,
+        mutations: MutationStats,
+        opts: &Opts,
+    ) -> Self {
+        let genes: Vec<Option<f64>> = genome::continuous()
+            .map(|def| {
+                if def.is_enabled(opts) {
+                    Some(0.0)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let mut all = StatsInner(genes.clone());
+        let mut herbivores = StatsInner(genes.clone());
+        let mut carnivores = StatsInner(genes.clone());
+        let mut scavengers = StatsInner(genes.clone());
+        let mut omnivores = StatsInner(genes);
 
         let mut herbivores_count = 0;
         let mut carnivores_count = 0;
+        let mut scavengers_count = 0;
+        let mut omnivores_count = 0;
 
         for c in creatures {
-            all.speed += c.speed / creatures.len() as f64;
-            all.stamina += c.stamina / creatures.len() as f64;
-            all.fov += c.fov / creatures.len() as f64;
-            all.size += c.size / creatures.len() as f64;
-
-            match c.diet {
+            let group = match c.diet() {
                 Diet::Herbivore => {
-                    herbivores.speed += c.speed;
-                    herbivores.stamina += c.stamina;
-                    herbivores.fov += c.fov;
-                    herbivores.size += c.size;
-
                     herbivores_count += 1;
+                    &mut herbivores
                 }
                 Diet::Carnivore => {
-                    carnivores.speed += c.speed;
-                    carnivores.stamina += c.stamina;
-                    carnivores.fov += c.fov;
-                    carnivores.size += c.size;
-
                     carnivores_count += 1;
+                    &mut carnivores
                 }
-            }
-        }
+                Diet::Scavenger => {
+                    scavengers_count += 1;
+                    &mut scavengers
+                }
+                Diet::Omnivore => {
+                    omnivores_count += 1;
+                    &mut omnivores
+                }
+            };
 
-        if carnivores_count > 0 {
-            carnivores.speed /= carnivores_count as f64;
-            carnivores.stamina /= carnivores_count as f64;
-            carnivores.fov /= carnivores_count as f64;
-            carnivores.size /= carnivores_count as f64;
-        } else {
-            carnivores.speed = 0.5;
-            carnivores.stamina = 0.5;
-            carnivores.fov = 0.5;
-            carnivores.size = 0.5;
+            for (i, value) in c.genome.continuous().enumerate() {
+                if let (Some(all), Some(group)) = (&mut all.0[i], &mut group.0[i]) {
+                    *all += value / creatures.len() as f64;
+                    *group += value;
+                }
+            }
         }
 
-        if herbivores_count > 0 {
-            herbivores.speed /= herbivores_count as f64;
-            herbivores.stamina /= herbivores_count as f64;
-            herbivores.fov /= herbivores_count as f64;
-            herbivores.size /= herbivores_count as f64;
-        } else {
-            herbivores.speed = 0.5;
-            herbivores.stamina = 0.5;
-            herbivores.fov = 0.5;
-            herbivores.size = 0.5;
-        }
+        carnivores.average(carnivores_count);
+        herbivores.average(herbivores_count);
+        scavengers.average(scavengers_count);
+        omnivores.average(omnivores_count);
+        let species = speciation::sizes(creatures);
 
         Self {
             all,
             count: creatures.len(),
+            cost: creatures
+                .iter()
+                .map(|c| c.genome.cost(Budget::Traits, opts))
+                .sum::<f64>()
+                / creatures.len().max(1) as f64,
 
             herbivores,
             herbivores_count,
 
             carnivores,
             carnivores_count,
+
+            scavengers,
+            scavengers_count,
+
+            omnivores,
+            omnivores_count,
+
+            deaths: DeathStats::collect(deaths),
+            meals,
+            mutations,
+            founders: Genealogy::surviving_founders(creatures),
+            species_count: species.len(),
+            species,
+            heterozygosity: if opts.diploid {
+                Some(Heterozygosity::collect(creatures, opts))
+            } else {
+                None
+            },
         }
     }
 
-    pub fn write(stats: &[Self]) -> Result<(), Error> {
+    /// Draws the charts to the SVG file and writes the options that produced them, along with
+    /// the raw series as CSV and JSON, next to it
+    pub fn write(stats: &[Self], path: &Path, title: &str, opts: &Opts) -> Result<(), Error> {
         const RED: RGBColor = RGBColor(222, 66, 66);
         const GREEN: RGBColor = RGBColor(66, 222, 66);
         const BLUE: RGBColor = RGBColor(66, 66, 222);
         const GREY: RGBColor = RGBColor(111, 111, 111);
+        const PURPLE: RGBColor = RGBColor(166, 66, 200);
+
+        const PALETTE: [RGBColor; 5] = [RED, GREEN, BLUE, GREY, PURPLE];
 
         macro_rules! draw {
             ($chart:expr, $stats:expr) => {
                 $chart.configure_mesh().draw()?;
 
-                $chart
-                    .draw_series(LineSeries::new(
-                        $stats.iter().map(|s| s.speed).enumerate(),
-                        &RED,
-                    ))?
-                    .label("Vitesse")
-                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &RED));
-
-                $chart
-                    .draw_series(LineSeries::new(
-                        $stats.iter().map(|s| s.stamina).enumerate(),
-                        &GREEN,
-                    ))?
-                    .label("Endurance")
-                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREEN));
-
-                $chart
-                    .draw_series(LineSeries::new(
-                        $stats.iter().map(|s| s.fov).enumerate(),
-                        &BLUE,
-                    ))?
-                    .label("Champ de vision")
-                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &BLUE));
-
-                $chart
-                    .draw_series(LineSeries::new(
-                        $stats.iter().map(|s| s.size).enumerate(),
-                        &GREY,
-                    ))?
-                    .label("Taille")
-                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREY));
+                for (i, def) in genome::continuous().enumerate() {
+                    if !def.is_enabled(opts) {
+                        continue;
+                    }
+                    let colour = PALETTE[i % PALETTE.len()];
+                    $chart
+                        .draw_series(LineSeries::new(
+                            $stats
+                                .iter()
+                                .map(|s| s.0[i].unwrap_or_default())
+                                .enumerate(),
+                            &colour,
+                        ))?
+                        .label(def.label)
+                        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &colour));
+                }
 
                 $chart
                     .configure_series_labels()
@@ -148,15 +239,20 @@ impl Stats {
             };
         }
 
-        let title = Local::now().format("%Y-%m-%d-%H-%M-%S");
+        opts.write(&path.with_extension("toml"))?;
 
-        fs::create_dir_all("stats")?;
-        let path = Path::new("stats").join(format!("{}.svg", title));
+        let rows = Self::rows(stats, opts)?;
+        serde_json::to_writer_pretty(
+            BufWriter::new(File::create(path.with_extension("json"))?),
+            &rows,
+        )?;
+        write_csv(&rows, &path.with_extension("csv"))?;
 
-        let mut root = SVGBackend::new(&path, (1920, 1080)).into_drawing_area();
+        let mut root = SVGBackend::new(path, (1920, 2160)).into_drawing_area();
         root.fill(&WHITE)?;
         root = root.margin(32, 32, 32, 32);
-        let quadrants = root.split_evenly((2, 2));
+        root = root.titled(title, ("sans-serif", 24).into_font())?;
+        let quadrants = root.split_evenly((4, 3));
 
         let mut all = ChartBuilder::on(&quadrants[0])
             .caption("Caractéristiques", ("sans-serif", 32).into_font())
@@ -164,7 +260,7 @@ impl Stats {
             .x_label_area_size(16)
             .y_label_area_size(0)
             .build_cartesian_2d(0..stats.len(), 0f64..2f64)?;
-        let stats_all: Vec<StatsInner> = stats.iter().map(|s| s.all).collect();
+        let stats_all: Vec<&StatsInner> = stats.iter().map(|s| &s.all).collect();
         draw!(all, stats_all);
 
         let mut diets = ChartBuilder::on(&quadrants[1])
@@ -179,7 +275,7 @@ impl Stats {
             .draw_series(LineSeries::new(
                 stats
                     .iter()
-                    .map(|s| s.herbivores_count as f64 / s.count as f64)
+                    .map(|s| s.herbivores_count as f64 / s.count.max(1) as f64)
                     .enumerate(),
                 &GREEN,
             ))?
@@ -189,19 +285,43 @@ impl Stats {
             .draw_series(LineSeries::new(
                 stats
                     .iter()
-                    .map(|s| s.carnivores_count as f64 / s.count as f64)
+                    .map(|s| s.carnivores_count as f64 / s.count.max(1) as f64)
                     .enumerate(),
                 &RED,
             ))?
             .label("Carnivores")
             .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &RED));
+        if stats.iter().any(|s| s.scavengers_count > 0) {
+            diets
+                .draw_series(LineSeries::new(
+                    stats
+                        .iter()
+                        .map(|s| s.scavengers_count as f64 / s.count.max(1) as f64)
+                        .enumerate(),
+                    &BLUE,
+                ))?
+                .label("Charognards")
+                .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &BLUE));
+        }
+        if stats.iter().any(|s| s.omnivores_count > 0) {
+            diets
+                .draw_series(LineSeries::new(
+                    stats
+                        .iter()
+                        .map(|s| s.omnivores_count as f64 / s.count.max(1) as f64)
+                        .enumerate(),
+                    &PURPLE,
+                ))?
+                .label("Omnivores")
+                .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &PURPLE));
+        }
         diets
             .configure_series_labels()
             .background_style(&WHITE.mix(0.75))
             .border_style(&BLACK)
             .draw()?;
 
-        let mut herbivores = ChartBuilder::on(&quadrants[2])
+        let mut herbivores = ChartBuilder::on(&quadrants[3])
             .caption(
                 "Caractéristiques (herbivores)",
                 ("sans-serif", 32).into_font(),
@@ -210,10 +330,10 @@ impl Stats {
             .x_label_area_size(16)
             .y_label_area_size(0)
             .build_cartesian_2d(0..stats.len(), 0f64..2f64)?;
-        let stats_herbivores: Vec<StatsInner> = stats.iter().map(|s| s.herbivores).collect();
+        let stats_herbivores: Vec<&StatsInner> = stats.iter().map(|s| &s.herbivores).collect();
         draw!(herbivores, stats_herbivores);
 
-        let mut carnivores = ChartBuilder::on(&quadrants[3])
+        let mut carnivores = ChartBuilder::on(&quadrants[4])
             .caption(
                 "Caractéristiques (carnivores)",
                 ("sans-serif", 32).into_font(),
@@ -222,9 +342,614 @@ impl Stats {
             .x_label_area_size(16)
             .y_label_area_size(0)
             .build_cartesian_2d(0..stats.len(), 0f64..2f64)?;
-        let stats_carnivores: Vec<StatsInner> = stats.iter().map(|s| s.carnivores).collect();
+        let stats_carnivores: Vec<&StatsInner> = stats.iter().map(|s| &s.carnivores).collect();
         draw!(carnivores, stats_carnivores);
 
+        let max_deaths = stats
+            .iter()
+            .map(|s| {
+                let killers = s.deaths.killers.values().copied().max().unwrap_or(0);
+                s.deaths
+                    .starved
+                    .max(s.deaths.eaten)
+                    .max(s.deaths.scavenged)
+                    .max(killers)
+            })
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let mut deaths = ChartBuilder::on(&quadrants[2])
+            .caption("Causes de mortalité", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(32)
+            .build_cartesian_2d(0..stats.len(), 0..max_deaths)?;
+
+        deaths.configure_mesh().draw()?;
+        deaths
+            .draw_series(LineSeries::new(
+                stats.iter().map(|s| s.deaths.starved).enumerate(),
+                &GREY,
+            ))?
+            .label("Faim")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREY));
+        deaths
+            .draw_series(LineSeries::new(
+                stats.iter().map(|s| s.deaths.eaten).enumerate(),
+                &RED,
+            ))?
+            .label("Prédation")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &RED));
+        deaths
+            .draw_series(LineSeries::new(
+                stats.iter().map(|s| s.deaths.scavenged).enumerate(),
+                &BLUE,
+            ))?
+            .label("Charogne")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &BLUE));
+        // Creatures eaten alive or as a carcass, by diet of the creature that ate them, in the
+        // colour of the eyes of that diet
+        for diet in Diet::ALL {
+            if !stats.iter().any(|s| s.deaths.killers.contains_key(diet)) {
+                continue;
+            }
+            let (label, colour) = match diet {
+                Diet::Herbivore => ("Mangées par des herbivores", RGBColor(33, 122, 33)),
+                Diet::Carnivore => ("Mangées par des carnivores", RGBColor(122, 33, 33)),
+                Diet::Scavenger => ("Mangées par des charognards", RGBColor(33, 33, 122)),
+                Diet::Omnivore => ("Mangées par des omnivores", RGBColor(122, 122, 33)),
+            };
+            deaths
+                .draw_series(LineSeries::new(
+                    stats
+                        .iter()
+                        .map(|s| s.deaths.killers.get(diet).copied().unwrap_or(0))
+                        .enumerate(),
+                    &colour,
+                ))?
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &colour));
+        }
+        deaths
+            .configure_series_labels()
+            .background_style(&WHITE.mix(0.75))
+            .border_style(&BLACK)
+            .draw()?;
+
+        let max_delta = stats.iter().map(|s| s.mutations.delta).fold(1.0, f64::max);
+        let genes = genome::GENES
+            .iter()
+            .filter(|def| def.is_enabled(opts))
+            .count();
+        let mut mutations = ChartBuilder::on(&quadrants[5])
+            .caption("Mutations", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(32)
+            .build_cartesian_2d(0..stats.len(), 0f64..max_delta)?;
+
+        mutations.configure_mesh().draw()?;
+        mutations
+            .draw_series(LineSeries::new(
+                stats
+                    .iter()
+                    .map(|s| s.mutations.creatures as f64 / s.count.max(1) as f64)
+                    .enumerate(),
+                &GREY,
+            ))?
+            .label("Créatures mutées")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREY));
+        mutations
+            .draw_series(LineSeries::new(
+                stats
+                    .iter()
+                    .map(|s| {
+                        s.mutations.genes as f64 / (s.mutations.creatures.max(1) * genes) as f64
+                    })
+                    .enumerate(),
+                &GREEN,
+            ))?
+            .label("Gènes mutés")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREEN));
+        mutations
+            .draw_series(LineSeries::new(
+                stats.iter().map(|s| s.mutations.delta).enumerate(),
+                &RED,
+            ))?
+            .label("Variation moyenne")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &RED));
+        mutations
+            .configure_series_labels()
+            .background_style(&WHITE.mix(0.75))
+            .border_style(&BLACK)
+            .draw()?;
+
+        let max_founders = stats.iter().map(|s| s.founders).max().unwrap_or(0) + 1;
+        let mut founders = ChartBuilder::on(&quadrants[6])
+            .caption("Lignées fondatrices", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(32)
+            .build_cartesian_2d(0..stats.len(), 0..max_founders)?;
+
+        founders.configure_mesh().draw()?;
+        founders.draw_series(LineSeries::new(
+            stats.iter().map(|s| s.founders).enumerate(),
+            &GREY,
+        ))?;
+
+        let max_species = stats.iter().map(|s| s.species_count).max().unwrap_or(0) + 1;
+        let mut species_count = ChartBuilder::on(&quadrants[7])
+            .caption("Espèces", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(32)
+            .build_cartesian_2d(0..stats.len(), 0..max_species)?;
+
+        species_count.configure_mesh().draw()?;
+        species_count.draw_series(LineSeries::new(
+            stats.iter().map(|s| s.species_count).enumerate(),
+            &GREY,
+        ))?;
+
+        let mut species = ChartBuilder::on(&quadrants[8])
+            .caption("Taille des espèces", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(0)
+            .build_cartesian_2d(0..stats.len(), 0f64..1f64)?;
+        species.configure_mesh().draw()?;
+
+        // Stacked areas, drawn from the top so that every species covers the ones above it
+        let mut ids: Vec<u64> = stats
+            .iter()
+            .flat_map(|s| s.species.keys().copied())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut tops = vec![0.0; stats.len()];
+        let mut layers = Vec::with_capacity(ids.len());
+        for &id in &ids {
+            for (top, s) in tops.iter_mut().zip(stats) {
+                *top += s.species.get(&id).copied().unwrap_or(0) as f64 / s.count.max(1) as f64;
+            }
+            layers.push((id, tops.clone()));
+        }
+        for (id, tops) in layers.into_iter().rev() {
+            let colour = HSLColor((id as f64 * 0.618_034).fract(), 0.6, 0.6);
+            species.draw_series(AreaSeries::new(tops.into_iter().enumerate(), 0.0, &colour))?;
+        }
+
+        let mut heterozygosity = ChartBuilder::on(&quadrants[9])
+            .caption("Hétérozygotie", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(0)
+            .build_cartesian_2d(0..stats.len(), 0f64..1f64)?;
+        heterozygosity.configure_mesh().draw()?;
+
+        if stats.iter().any(|s| s.heterozygosity.is_some()) {
+            let series = |f: fn(&Heterozygosity) -> Option<f64>| {
+                stats
+                    .iter()
+                    .map(move |s| s.heterozygosity.as_ref().and_then(f).unwrap_or_default())
+                    .enumerate()
+            };
+            if genome::GENES[DIET].is_enabled(opts) {
+                heterozygosity
+                    .draw_series(LineSeries::new(series(|h| h.diet), &GREEN))?
+                    .label("Alimentation hétérozygote")
+                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREEN));
+                heterozygosity
+                    .draw_series(LineSeries::new(series(|h| h.carnivore_alleles), &RED))?
+                    .label("Allèles carnivores")
+                    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &RED));
+            }
+            heterozygosity
+                .draw_series(LineSeries::new(series(|h| Some(h.traits)), &BLUE))?
+                .label("Écart des allèles")
+                .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &BLUE));
+            heterozygosity
+                .configure_series_labels()
+                .background_style(&WHITE.mix(0.75))
+                .border_style(&BLACK)
+                .draw()?;
+        }
+
+        let max_params = stats
+            .iter()
+            .map(|s| s.mutations.probability.max(s.mutations.scale))
+            .fold(0.0, f64::max)
+            * 1.1;
+        let mut params = ChartBuilder::on(&quadrants[10])
+            .caption("Paramètres de mutation", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(32)
+            .build_cartesian_2d(0..stats.len(), 0f64..max_params.max(0.1))?;
+
+        params.configure_mesh().draw()?;
+        params
+            .draw_series(LineSeries::new(
+                stats.iter().map(|s| s.mutations.probability).enumerate(),
+                &GREEN,
+            ))?
+            .label("Probabilité")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &GREEN));
+        params
+            .draw_series(LineSeries::new(
+                stats.iter().map(|s| s.mutations.scale).enumerate(),
+                &BLUE,
+            ))?
+            .label("Ampleur")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &BLUE));
+        params
+            .configure_series_labels()
+            .background_style(&WHITE.mix(0.75))
+            .border_style(&BLACK)
+            .draw()?;
+
+        let max_meals = stats
+            .iter()
+            .flat_map(|s| s.meals.0.iter().copied())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let mut meals = ChartBuilder::on(&quadrants[11])
+            .caption("Nourriture mangée", ("sans-serif", 32).into_font())
+            .margin(32)
+            .x_label_area_size(16)
+            .y_label_area_size(32)
+            .build_cartesian_2d(0..stats.len(), 0..max_meals)?;
+
+        meals.configure_mesh().draw()?;
+        for (i, def) in FOOD_KINDS.iter().enumerate() {
+            let colour = RGBColor(def.colour.0, def.colour.1, def.colour.2);
+            meals
+                .draw_series(LineSeries::new(
+                    stats.iter().map(|s| s.meals.0[i]).enumerate(),
+                    &colour,
+                ))?
+                .label(def.label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 24, y)], &colour));
+        }
+        meals
+            .configure_series_labels()
+            .background_style(&WHITE.mix(0.75))
+            .border_style(&BLACK)
+            .draw()?;
+
+        Ok(())
+    }
+
+    /// One row per generation with every field, nested fields being joined with `_`, along with
+    /// the tick of every sample in steady-state mode
+    fn rows(stats: &[Self], opts: &Opts) -> Result<Vec<Map<String, Value>>, Error> {
+        stats
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let mut row = Map::new();
+                row.insert("generation".to_owned(), (i + 1).into());
+                if opts.evolution == Evolution::SteadyState {
+                    row.insert(
+                        "tick".to_owned(),
+                        ((i + 1) * opts.sample_interval.max(1)).into(),
+                    );
+                }
+                flatten(&mut row, "", serde_json::to_value(s)?);
+                Ok(row)
+            })
+            .collect()
+    }
+}
+
+/// Path of a new SVG file in the `stats` folder, named after the current time and the seed
+pub fn path(seed: u64) -> Result<PathBuf, Error> {
+    let title = Local::now().format("%Y-%m-%d-%H-%M-%S");
+
+    fs::create_dir_all("stats")?;
+    Ok(Path::new("stats").join(format!("{}-{}.svg", title, seed)))
+}
+
+fn flatten(row: &mut Map<String, Value>, prefix: &str, value: Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if prefix.is_empty() {
+                    flatten(row, &key, value);
+                } else {
+                    flatten(row, &format!("{}_{}", prefix, key), value);
+                }
+            }
+        }
+        value => {
+            row.insert(prefix.to_owned(), value);
+        }
+    }
+}
+
+/// Columns are the union of the fields of every row, since maps like the killers by diet
+/// only contain the keys that were encountered during that generation
+fn write_csv(rows: &[Map<String, Value>], path: &Path) -> Result<(), Error> {
+    let mut columns: Vec<&str> = Vec::new();
+    for key in rows.iter().flat_map(|r| r.keys()) {
+        if !columns.contains(&key.as_str()) {
+            columns.push(key);
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", columns.join(","))?;
+    for row in rows {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|&c| match row.get(c) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(v) => v.to_string(),
+            })
+            .collect();
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl DeathStats {
+    fn collect(deaths: &[Death]) -> Self {
+        let mut stats = Self::default();
+        for d in deaths {
+            match d.cause {
+                DeathCause::Starved => stats.starved += 1,
+                DeathCause::Eaten => stats.eaten += 1,
+                DeathCause::Scavenged => stats.scavenged += 1,
+            }
+            if let Some(killer) = d.killer {
+                *stats.killers.entry(killer).or_insert(0) += 1;
+            }
+        }
+        stats
+    }
+}
+
+impl Heterozygosity {
+    fn collect(creatures: &[Creature], opts: &Opts) -> Self {
+        let carnivore = Diet::ALL
+            .iter()
+            .position(|&d| d == Diet::Carnivore)
+            .unwrap();
+        let (mut diet, mut carnivore_alleles, mut traits, mut genes) = (0, 0, 0.0, 0);
+
+        for c in creatures {
+            for (i, def) in genome::GENES.iter().enumerate() {
+                if !def.is_enabled(opts) {
+                    continue;
+                }
+                match (&def.kind, c.genome.alleles(i)) {
+                    (
+                        GeneKind::Continuous { bounds, .. },
+                        [Gene::Continuous(a), Gene::Continuous(b)],
+                    ) => {
+                        traits += (a - b).abs() / (bounds.1 - bounds.0);
+                        genes += 1;
+                    }
+                    (_, [Gene::Categorical(a), Gene::Categorical(b)]) if i == DIET => {
+                        if a != b {
+                            diet += 1;
+                        }
+                        carnivore_alleles += (a == carnivore) as usize + (b == carnivore) as usize;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let count = creatures.len().max(1) as f64;
+        let (diet, carnivore_alleles) = if genome::GENES[DIET].is_enabled(opts) {
+            (
+                Some(diet as f64 / count),
+                Some(carnivore_alleles as f64 / (count * 2.0)),
+            )
+        } else {
+            (None, None)
+        };
+        Self {
+            diet,
+            carnivore_alleles,
+            traits: traits / genes.max(1) as f64,
+        }
+    }
+}
+
+impl StatsInner {
+    /// Turns sums into means, or an arbitrary middle value when there were no creatures
+    fn average(&mut self, count: usize) {
+        for value in self.0.iter_mut().flatten() {
+            if count > 0 {
+                *value /= count as f64;
+            } else {
+                *value = 0.5;
+            }
+        }
+    }
+}
+
+impl Serialize for StatsInner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.iter().flatten().count()))?;
+        for (def, mean) in genome::continuous().zip(&self.0) {
+            if let Some(mean) = mean {
+                map.serialize_entry(def.name, mean)?;
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for StatsInner {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let means = BTreeMap::<String, f64>::deserialize(deserializer)?;
+        Ok(Self(
+            genome::continuous()
+                .map(|def| means.get(def.name).copied())
+                .collect(),
+        ))
+    }
+}
+
+impl Meals {
+    pub fn collect(creatures: &[LivingCreature]) -> Self {
+        let mut meals = vec![0; FOOD_KINDS.len()];
+        for c in creatures {
+            for (total, count) in meals.iter_mut().zip(&c.meals) {
+                *total += count;
+            }
+        }
+        Self(meals)
+    }
+
+    /// Food eaten by several groups of creatures put together
+    pub fn sum(meals: &[Self]) -> Self {
+        Self(
+            (0..FOOD_KINDS.len())
+                .map(|i| meals.iter().map(|m| m.0[i]).sum())
+                .collect(),
+        )
+    }
+}
+
+impl Serialize for Meals {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (def, count) in FOOD_KINDS.iter().zip(&self.0) {
+            map.serialize_entry(def.name, count)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Meals {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let counts = BTreeMap::<String, usize>::deserialize(deserializer)?;
+        Ok(Self(
+            FOOD_KINDS
+                .iter()
+                .map(|def| counts.get(def.name).copied().unwrap_or_default())
+                .collect(),
+        ))
+    }
+}
+
+impl MutationStats {
+    /// Statistics of several groups of creatures put together
+    pub fn sum(stats: &[Self]) -> Self {
+        let mut sum = Self::default();
+        for s in stats {
+            if sum.creatures == 0 {
+                sum.delta = s.delta;
+            } else if s.creatures > 0 {
+                sum.delta = (sum.delta * sum.creatures as f64 + s.delta * s.creatures as f64)
+                    / (sum.creatures + s.creatures) as f64;
+            }
+            if sum.population == 0 {
+                sum.probability = s.probability;
+                sum.scale = s.scale;
+            } else if s.population > 0 {
+                let total = (sum.population + s.population) as f64;
+                let (a, b) = (sum.population as f64, s.population as f64);
+                sum.probability = (sum.probability * a + s.probability * b) / total;
+                sum.scale = (sum.scale * a + s.scale * b) / total;
+            }
+            sum.population += s.population;
+            sum.creatures += s.creatures;
+            sum.genes += s.genes;
+            sum.diet_flips += s.diet_flips;
+        }
+        sum
+    }
+
+    /// Records the mutation parameters used for every creature of the generation
+    pub fn params(&mut self, params: &[MutationParams]) {
+        let count = params.len().max(1) as f64;
+        self.probability = params.iter().map(|p| p.probability).sum::<f64>() / count;
+        self.scale = params.iter().map(|p| p.scale).sum::<f64>() / count;
+        self.population = params.len();
+    }
+
+    pub fn record(&mut self, before: &Creature, after: &Creature, genes: usize, opts: &Opts) {
+        let delta: f64 = before
+            .genome
+            .continuous_enabled(opts)
+            .zip(after.genome.continuous_enabled(opts))
+            .map(|(before, after)| (after - before).abs())
+            .sum();
+
+        self.delta = (self.delta * self.creatures as f64 + delta) / (self.creatures + 1) as f64;
+        self.creatures += 1;
+        self.genes += genes;
+        if after.diet() != before.diet() {
+            self.diet_flips += 1;
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} créatures ({} herbivores, {} carnivores",
+            self.count, self.herbivores_count, self.carnivores_count,
+        )?;
+        if self.scavengers_count > 0 {
+            write!(f, ", {} charognards", self.scavengers_count)?;
+        }
+        if self.omnivores_count > 0 {
+            write!(f, ", {} omnivores", self.omnivores_count)?;
+        }
+        write!(f, ") -")?;
+        let means = genome::continuous()
+            .zip(&self.all.0)
+            .filter_map(|(def, mean)| mean.map(|mean| (def, mean)));
+        for (i, (def, mean)) in means.enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} {} {:.3}", separator, def.label.to_lowercase(), mean)?;
+        }
+        write!(
+            f,
+            " - morts : {} de faim, {} par prédation, {} en charogne - mutations : {} créatures, {} gènes, variation {:.3}, probabilité {:.3}, ampleur {:.3} - {} espèces",
+            self.deaths.starved,
+            self.deaths.eaten,
+            self.deaths.scavenged,
+            self.mutations.creatures,
+            self.mutations.genes,
+            self.mutations.delta,
+            self.mutations.probability,
+            self.mutations.scale,
+            self.species_count,
+        )?;
+        let meals: Vec<String> = FOOD_KINDS
+            .iter()
+            .zip(&self.meals.0)
+            .filter(|(_, &count)| count > 0)
+            .map(|(def, count)| format!("{} {}", count, def.label.to_lowercase()))
+            .collect();
+        if !meals.is_empty() {
+            write!(f, " - nourriture : {}", meals.join(", "))?;
+        }
+        if let Some(Heterozygosity {
+            diet: Some(diet),
+            carnivore_alleles: Some(carnivore_alleles),
+            ..
+        }) = &self.heterozygosity
+        {
+            write!(
+                f,
+                " - hétérozygotes : {:.1} %, allèles carnivores {:.1} %",
+                diet * 100.0,
+                carnivore_alleles * 100.0
+            )?;
+        }
         Ok(())
     }
 }
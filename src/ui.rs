@@ -21,7 +21,8 @@ type UI<'a> = (
     (Texture<'a>, Rect),
 );
 pub fn render<'a>(
-    generation: usize,
+    progress: &str,
+    island: (usize, usize),
     paused: bool,
     speed: usize,
     debug: bool,
@@ -30,7 +31,11 @@ pub fn render<'a>(
 ) -> Result<UI<'a>, Error> {
     const PADDING: i32 = 16;
 
-    let l1 = format!("Génération {}", generation);
+    let l1 = if island.1 > 1 {
+        format!("{} - île {}/{}", progress, island.0 + 1, island.1)
+    } else {
+        progress.to_owned()
+    };
     let l2 = format!("{} (x{})", if paused { "Pause" } else { "Play" }, speed);
     let l3 = if debug {
         "Vue détaillée"
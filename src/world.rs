@@ -0,0 +1,128 @@
+use clap::ArgEnum;
+use nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Topology {
+    /// Opposite edges are connected, sensing takes the shortest path across them
+    Torus,
+    /// Creatures bounce off the edges
+    Bounce,
+    /// Creatures stop against the edges and slide along them
+    Walls,
+    /// No edges, creatures can wander outside of the initial area
+    Open,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct World {
+    pub x: (f64, f64),
+    pub y: (f64, f64),
+    pub topology: Topology,
+}
+
+impl World {
+    pub fn width(&self) -> f64 {
+        self.x.1 - self.x.0
+    }
+
+    pub fn height(&self) -> f64 {
+        self.y.1 - self.y.0
+    }
+
+    pub fn wraps(&self) -> bool {
+        self.topology == Topology::Torus
+    }
+
+    /// Vector going from `from` to `to`, through the edges if that is shorter on a torus
+    pub fn vector(&self, from: Point2<f64>, to: Point2<f64>) -> Vector2<f64> {
+        let mut vector = to - from;
+        if self.wraps() {
+            vector.x = wrap_offset(vector.x, self.width());
+            vector.y = wrap_offset(vector.y, self.height());
+        }
+        vector
+    }
+
+    pub fn distance(&self, from: Point2<f64>, to: Point2<f64>) -> f64 {
+        self.vector(from, to).magnitude()
+    }
+
+    /// Brings a position that just moved by `direction` back inside the world
+    pub fn constrain(&self, position: &mut Point2<f64>, direction: &mut Vector2<f64>) {
+        match self.topology {
+            Topology::Torus => {
+                if position.x < self.x.0 {
+                    position.x = self.x.1 - (self.x.0 - position.x);
+                } else if position.x > self.x.1 {
+                    position.x = self.x.0 + (position.x - self.x.1);
+                }
+                if position.y < self.y.0 {
+                    position.y = self.y.1 - (self.y.0 - position.y);
+                } else if position.y > self.y.1 {
+                    position.y = self.y.0 + (position.y - self.y.1);
+                }
+            }
+
+            Topology::Bounce => {
+                if position.x < self.x.0 {
+                    position.x = self.x.0 + (self.x.0 - position.x);
+                    direction.x = direction.x.abs();
+                } else if position.x > self.x.1 {
+                    position.x = self.x.1 - (position.x - self.x.1);
+                    direction.x = -direction.x.abs();
+                }
+                if position.y < self.y.0 {
+                    position.y = self.y.0 + (self.y.0 - position.y);
+                    direction.y = direction.y.abs();
+                } else if position.y > self.y.1 {
+                    position.y = self.y.1 - (position.y - self.y.1);
+                    direction.y = -direction.y.abs();
+                }
+            }
+
+            Topology::Walls => {
+                position.x = position.x.max(self.x.0).min(self.x.1);
+                position.y = position.y.max(self.y.0).min(self.y.1);
+            }
+
+            Topology::Open => (),
+        }
+    }
+
+    /// Every position at which a circle of the given radius must be drawn to appear on screen,
+    /// which includes its copies on the other side of the edges on a torus
+    pub fn images(&self, position: Point2<f64>, radius: f64) -> Vec<Point2<f64>> {
+        if !self.wraps() {
+            return vec![position];
+        }
+
+        let offsets = |p: f64, bounds: (f64, f64)| {
+            let mut offsets = vec![0.0];
+            if p - radius < bounds.0 {
+                offsets.push(bounds.1 - bounds.0);
+            }
+            if p + radius > bounds.1 {
+                offsets.push(bounds.0 - bounds.1);
+            }
+            offsets
+        };
+
+        let xs = offsets(position.x, self.x);
+        let ys = offsets(position.y, self.y);
+        xs.iter()
+            .flat_map(|&dx| ys.iter().map(move |&dy| position + Vector2::new(dx, dy)))
+            .collect()
+    }
+}
+
+fn wrap_offset(offset: f64, size: f64) -> f64 {
+    if offset > size / 2.0 {
+        offset - size
+    } else if offset < -size / 2.0 {
+        offset + size
+    } else {
+        offset
+    }
+}